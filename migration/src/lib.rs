@@ -1,6 +1,13 @@
 pub use sea_orm_migration::prelude::*;
 
 mod m20241219_091936_create_users_table;
+mod m20250108_143012_create_recordings_table;
+mod m20250121_083544_add_parent_id_to_recordings;
+mod m20250128_101500_create_snapshots_table;
+mod m20250204_093000_add_previews_to_recordings;
+mod m20250211_090000_create_sessions_table;
+mod m20250218_090000_add_role_to_users;
+mod m20250304_090000_create_api_keys_table;
 
 pub struct Migrator;
 
@@ -9,6 +16,13 @@ impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
         vec![
             Box::new(m20241219_091936_create_users_table::Migration),
+            Box::new(m20250108_143012_create_recordings_table::Migration),
+            Box::new(m20250121_083544_add_parent_id_to_recordings::Migration),
+            Box::new(m20250128_101500_create_snapshots_table::Migration),
+            Box::new(m20250204_093000_add_previews_to_recordings::Migration),
+            Box::new(m20250211_090000_create_sessions_table::Migration),
+            Box::new(m20250218_090000_add_role_to_users::Migration),
+            Box::new(m20250304_090000_create_api_keys_table::Migration),
         ]
     }
 }
@@ -0,0 +1,97 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Recording::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(Recording::Id).uuid().not_null().primary_key())
+                    .col(ColumnDef::new(Recording::OwnerId).uuid().null())
+                    .col(ColumnDef::new(Recording::Source).string().not_null())
+                    .col(ColumnDef::new(Recording::FilePath).string().not_null())
+                    .col(ColumnDef::new(Recording::Codec).string().not_null())
+                    .col(ColumnDef::new(Recording::Container).string().not_null())
+                    .col(ColumnDef::new(Recording::DurationMs).big_integer().null())
+                    .col(ColumnDef::new(Recording::SizeBytes).big_integer().null())
+                    .col(ColumnDef::new(Recording::Status).string().not_null())
+                    .col(ColumnDef::new(Recording::Error).text().null())
+                    .col(timestamp_with_time_zone(Recording::StartedAt))
+                    .col(timestamp_with_time_zone_null(Recording::FinishedAt))
+                    .col(
+                        timestamp_with_time_zone(Recording::CreatedAt)
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(
+                        timestamp_with_time_zone(Recording::UpdatedAt)
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-recording-owner_id")
+                            .from(Recording::Table, Recording::OwnerId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Recordings are listed by owner and looked up by status during recovery
+        manager
+            .create_index(
+                sea_query::Index::create()
+                    .name("idx-recording-owner_id")
+                    .table(Recording::Table)
+                    .col(Recording::OwnerId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                sea_query::Index::create()
+                    .name("idx-recording-status")
+                    .table(Recording::Table)
+                    .col(Recording::Status)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Recording::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Recording {
+    Table,
+    Id,
+    OwnerId,
+    Source,
+    FilePath,
+    Codec,
+    Container,
+    DurationMs,
+    SizeBytes,
+    Status,
+    Error,
+    StartedAt,
+    FinishedAt,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
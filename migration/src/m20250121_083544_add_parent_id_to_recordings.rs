@@ -0,0 +1,56 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Segments of a continuous recording reference their parent session
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Recording::Table)
+                    .add_column(ColumnDef::new(Recording::ParentId).uuid().null())
+                    .add_foreign_key(
+                        TableForeignKey::new()
+                            .name("fk-recording-parent_id")
+                            .from_tbl(Recording::Table)
+                            .from_col(Recording::ParentId)
+                            .to_tbl(Recording::Table)
+                            .to_col(Recording::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                sea_query::Index::create()
+                    .name("idx-recording-parent_id")
+                    .table(Recording::Table)
+                    .col(Recording::ParentId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Recording::Table)
+                    .drop_column(Recording::ParentId)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Recording {
+    Table,
+    Id,
+    ParentId,
+}
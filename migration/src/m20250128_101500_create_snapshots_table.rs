@@ -0,0 +1,69 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Snapshot::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(Snapshot::Id).uuid().not_null().primary_key())
+                    .col(ColumnDef::new(Snapshot::OwnerId).uuid().null())
+                    .col(ColumnDef::new(Snapshot::Source).string().not_null())
+                    .col(ColumnDef::new(Snapshot::FilePath).string().not_null())
+                    .col(ColumnDef::new(Snapshot::Format).string().not_null())
+                    .col(ColumnDef::new(Snapshot::SizeBytes).big_integer().not_null())
+                    .col(
+                        timestamp_with_time_zone(Snapshot::CreatedAt)
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-snapshot-owner_id")
+                            .from(Snapshot::Table, Snapshot::OwnerId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                sea_query::Index::create()
+                    .name("idx-snapshot-owner_id")
+                    .table(Snapshot::Table)
+                    .col(Snapshot::OwnerId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Snapshot::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Snapshot {
+    Table,
+    Id,
+    OwnerId,
+    Source,
+    FilePath,
+    Format,
+    SizeBytes,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
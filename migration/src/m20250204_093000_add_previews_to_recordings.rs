@@ -0,0 +1,39 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Thumbnails are stored as a JSON array of paths
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Recording::Table)
+                    .add_column(ColumnDef::new(Recording::PosterPath).string().null())
+                    .add_column(ColumnDef::new(Recording::ThumbnailPaths).text().null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Recording::Table)
+                    .drop_column(Recording::PosterPath)
+                    .drop_column(Recording::ThumbnailPaths)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Recording {
+    Table,
+    PosterPath,
+    ThumbnailPaths,
+}
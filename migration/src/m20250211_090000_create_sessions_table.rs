@@ -0,0 +1,81 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Session::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(Session::Id).uuid().not_null().primary_key())
+                    .col(ColumnDef::new(Session::UserId).uuid().not_null())
+                    .col(ColumnDef::new(Session::FamilyId).uuid().not_null())
+                    .col(ColumnDef::new(Session::TokenHash).string().not_null())
+                    .col(
+                        timestamp_with_time_zone(Session::CreatedAt)
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(timestamp_with_time_zone(Session::ExpiresAt))
+                    .col(timestamp_with_time_zone_null(Session::UsedAt))
+                    .col(timestamp_with_time_zone_null(Session::RevokedAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-session-user_id")
+                            .from(Session::Table, Session::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                sea_query::Index::create()
+                    .name("idx-session-user_id")
+                    .table(Session::Table)
+                    .col(Session::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                sea_query::Index::create()
+                    .name("idx-session-family_id")
+                    .table(Session::Table)
+                    .col(Session::FamilyId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Session::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Session {
+    Table,
+    Id,
+    UserId,
+    FamilyId,
+    TokenHash,
+    CreatedAt,
+    ExpiresAt,
+    UsedAt,
+    RevokedAt,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
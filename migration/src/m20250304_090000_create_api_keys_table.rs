@@ -0,0 +1,79 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Scopes are stored as a JSON array of names
+        manager
+            .create_table(
+                Table::create()
+                    .table(ApiKey::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(ApiKey::Id).uuid().not_null().primary_key())
+                    .col(ColumnDef::new(ApiKey::UserId).uuid().not_null())
+                    .col(ColumnDef::new(ApiKey::Name).string().not_null())
+                    .col(
+                        ColumnDef::new(ApiKey::Prefix)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(ApiKey::KeyHash).string().not_null())
+                    .col(ColumnDef::new(ApiKey::Scopes).text().not_null())
+                    .col(timestamp_with_time_zone_null(ApiKey::ExpiresAt))
+                    .col(timestamp_with_time_zone_null(ApiKey::LastUsedAt))
+                    .col(
+                        timestamp_with_time_zone(ApiKey::CreatedAt)
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-api_key-user_id")
+                            .from(ApiKey::Table, ApiKey::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                sea_query::Index::create()
+                    .name("idx-api_key-user_id")
+                    .table(ApiKey::Table)
+                    .col(ApiKey::UserId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ApiKey::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum ApiKey {
+    Table,
+    Id,
+    UserId,
+    Name,
+    Prefix,
+    KeyHash,
+    Scopes,
+    ExpiresAt,
+    LastUsedAt,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
@@ -1,12 +1,29 @@
+use std::path::Path;
 use std::sync::Arc;
 
-use axum::middleware;
+use axum::extract::Request;
+use axum::http::StatusCode;
+use axum::middleware::{self, Next};
+use axum::response::IntoResponse;
 use axum::{response::Response, routing::get_service, Router};
 use migration::{Migrator, MigratorTrait};
 use sea_orm::{Database, DatabaseConnection};
-use tower_http::services::ServeDir;
+use tower_http::services::{ServeDir, ServeFile};
 
 use crate::configuration::AppConfiguration;
+use crate::features::api_keys::api_key_service::ApiKeyService;
+use crate::features::auth::auth_middleware::authenticate;
+use crate::features::auth::auth_routes;
+use crate::features::auth::auth_service::{AuthService, AuthServiceError};
+use crate::features::recordings::recording_routes;
+use crate::features::recordings::recording_service::{
+    recover_interrupted_recordings, RecordingService,
+};
+use crate::features::snapshots::snapshot_routes;
+use crate::features::snapshots::snapshot_service::SnapshotService;
+use crate::features::streams::recording_manager::RecordingManager;
+use crate::features::streams::stream_routes;
+use crate::features::streams::stream_service::StreamService;
 use crate::features::users::user_routes;
 use crate::features::users::user_service::UserService;
 use crate::service::{ServiceProvider, ServiceType};
@@ -44,6 +61,12 @@ impl Application {
             // Apply pending migrations
             Migrator::up(&connection, None).await?;
 
+            // Settle recordings left unfinished by a previous run
+            let recovered = recover_interrupted_recordings(&connection).await?;
+            if recovered > 0 {
+                tracing::info!("Recovered {} interrupted recordings", recovered);
+            }
+
             self.state = ApplicationState {
                 connection: Some(Arc::new(connection)),
                 service_provider: Arc::new(ServiceProvider::new()),
@@ -53,20 +76,102 @@ impl Application {
         Ok(self)
     }
 
-    /// Builds the application.
-    pub fn build_router(&self) -> Router {
+    /// Builds the application, failing on an unusable auth configuration.
+    pub fn build_router(&self) -> Result<Router, AuthServiceError> {
+        let user_service = UserService::new(self.state.connection.clone());
+        let api_key_service =
+            ApiKeyService::new(self.state.connection.clone(), user_service.clone());
+        let auth_service = AuthService::new(
+            self.state.connection.clone(),
+            self.configuration.auth.clone(),
+            user_service.clone(),
+            api_key_service.clone(),
+        )?;
+        self.state
+            .service_provider
+            .add_service(ServiceType::UserService(user_service));
+        self.state
+            .service_provider
+            .add_service(ServiceType::ApiKeyService(api_key_service));
         self.state
             .service_provider
-            .add_service(ServiceType::UserService(UserService::new(
-                self.state.connection.clone(),
-            )));
+            .add_service(ServiceType::AuthService(auth_service.clone()));
+
+        let recording_manager = RecordingManager::new(self.configuration.media.clone());
+        let recording_service =
+            RecordingService::new(self.state.connection.clone(), recording_manager.clone());
+        recording_service.watch_recording_events();
+
+        // Before the continuous recordings, which then read from the buffers
+        for result in recording_manager.start_pre_roll_buffers() {
+            match result {
+                Ok(source) => tracing::info!("Pre-roll buffer started for source {}", source),
+                Err(err) => tracing::error!("Failed to start pre-roll buffer: {}", err),
+            }
+        }
+
+        for result in recording_manager.start_continuous_recordings() {
+            match result {
+                Ok(info) => tracing::info!(
+                    "Continuous recording {} started for source {}",
+                    info.id,
+                    info.source
+                ),
+                Err(err) => tracing::error!("Failed to start continuous recording: {}", err),
+            }
+        }
+
+        let snapshot_service = SnapshotService::new(
+            self.state.connection.clone(),
+            self.configuration.media.clone(),
+        );
+        let stream_service = StreamService::new(
+            self.configuration.media.clone(),
+            recording_manager.clone(),
+            snapshot_service.clone(),
+        );
+        match stream_service.start_rtsp_server() {
+            Ok(mounts) => {
+                for mount in mounts {
+                    tracing::info!("Source {} available at {}", mount.source, mount.url);
+                }
+            }
+            Err(err) => tracing::error!("Failed to start the RTSP server: {}", err),
+        }
+
+        self.state
+            .service_provider
+            .add_service(ServiceType::RecordingManager(recording_manager));
+        self.state
+            .service_provider
+            .add_service(ServiceType::StreamService(stream_service));
+        self.state
+            .service_provider
+            .add_service(ServiceType::RecordingService(recording_service));
+        self.state
+            .service_provider
+            .add_service(ServiceType::SnapshotService(snapshot_service));
 
         let router = Router::new()
+            .nest_service("/api/auth", auth_routes::routes(self.state.clone()))
             .nest_service("/api/users", user_routes::routes(self.state.clone()))
+            .nest_service(
+                "/api/recordings",
+                recording_routes::routes(self.state.clone()),
+            )
+            .nest_service("/api/streams", stream_routes::routes(self.state.clone()))
+            .nest_service(
+                "/api/snapshots",
+                snapshot_routes::routes(self.state.clone()),
+            )
+            // Only the API routes, the static fallback is added after
+            .layer(middleware::from_fn_with_state(auth_service, authenticate))
             .layer(middleware::map_response(main_response_mapper))
-            .fallback_service(routes_static());
+            .fallback_service(routes_static(
+                self.configuration.api.static_folder.as_deref(),
+            ));
 
-        router
+        Ok(router)
     }
 }
 
@@ -77,6 +182,63 @@ async fn main_response_mapper(response: Response) -> Response {
     response
 }
 
-fn routes_static() -> Router {
-    Router::new().nest_service("/", get_service(ServeDir::new("./")))
+/// Serves the web UI from `static_folder`. Paths without a matching file get
+/// its `index.html` so client-side routes survive a reload, except under
+/// `/api` where they are genuinely unknown.
+fn routes_static(static_folder: Option<&Path>) -> Router {
+    let Some(static_folder) = static_folder else {
+        return Router::new();
+    };
+
+    let index = ServeFile::new(static_folder.join("index.html"));
+    Router::new()
+        .nest_service(
+            "/",
+            get_service(ServeDir::new(static_folder).fallback(index)),
+        )
+        .layer(middleware::from_fn(reject_hidden_paths))
+}
+
+async fn reject_hidden_paths(request: Request, next: Next) -> Response {
+    let path = request.uri().path();
+    if path == "/api" || path.starts_with("/api/") || is_hidden_path(path) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+
+    next.run(request).await
+}
+
+/// Whether `path` names a dotfile, lies in a dot directory or climbs out of
+/// the static folder. Segments are checked before percent-decoding, an
+/// encoded leading dot is caught as well.
+fn is_hidden_path(path: &str) -> bool {
+    path.split('/').any(|segment| {
+        segment.starts_with('.')
+            || segment
+                .get(..3)
+                .is_some_and(|prefix| prefix.eq_ignore_ascii_case("%2e"))
+            || segment.contains('\\')
+            || segment.to_ascii_lowercase().contains("%2f")
+            || segment.to_ascii_lowercase().contains("%5c")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_hidden_path;
+
+    #[test]
+    fn it_should_reject_hidden_and_escaping_paths() {
+        assert!(is_hidden_path("/.env"));
+        assert!(is_hidden_path("/configuration/.git/config"));
+        assert!(is_hidden_path("/../Cargo.toml"));
+        assert!(is_hidden_path("/%2e%2e/configuration/default.toml"));
+        assert!(is_hidden_path("/%2Eenv"));
+        assert!(is_hidden_path("/assets/..%2f..%2f.env"));
+        assert!(is_hidden_path("/assets\\..\\.env"));
+
+        assert!(!is_hidden_path("/"));
+        assert!(!is_hidden_path("/index.html"));
+        assert!(!is_hidden_path("/assets/app.3f2a.js"));
+    }
 }
@@ -1,8 +1,20 @@
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
 use config::{Config, ConfigError, Environment, File};
 use dotenvy::dotenv;
+use jsonwebtoken::Algorithm;
 use sea_orm::ConnectOptions;
 use serde::Deserialize;
 
+use crate::features::streams::{
+    audio_source::AudioSource,
+    motion::MotionRegion,
+    pipeline::{AudioEncoder, Container, EncodingProfile},
+    video_source::VideoSource,
+};
+
 #[derive(Clone, Debug, Deserialize)]
 #[allow(unused)]
 pub struct AppConfiguration {
@@ -10,6 +22,7 @@ pub struct AppConfiguration {
     pub api: ApiConfiguration,
     pub media: MediaConfiguration,
     pub datasource: DataSourceConfiguration,
+    pub auth: AuthConfiguration,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -18,6 +31,57 @@ pub struct ApiConfiguration {
     pub local_ip: String,
     pub url: Option<String>,
     pub port: u16,
+    /// Folder of a built web UI served at the root, with its `index.html`
+    /// answering unknown paths. Nothing is served outside `/api` when unset.
+    pub static_folder: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AuthConfiguration {
+    /// Algorithm signing the access tokens, HS256 when unset.
+    #[serde(default)]
+    pub algorithm: Algorithm,
+    /// Key of the HMAC algorithms, keep it out of version control.
+    pub secret: Option<String>,
+    /// PEM private key of the RSA, ECDSA and EdDSA algorithms.
+    pub private_key: Option<PathBuf>,
+    /// PEM public key matching `private_key`.
+    pub public_key: Option<PathBuf>,
+    /// `iss` claim of the tokens, required when validating them.
+    #[serde(default = "default_token_issuer")]
+    pub issuer: String,
+    /// `aud` claim of the tokens, required when validating them.
+    #[serde(default = "default_token_audience")]
+    pub audience: String,
+    /// Lifetime of the access tokens in seconds.
+    #[serde(default = "default_access_token_ttl")]
+    pub access_token_ttl: u64,
+    /// Lifetime of the refresh tokens in seconds, renewed on each refresh.
+    #[serde(default = "default_refresh_token_ttl")]
+    pub refresh_token_ttl: u64,
+    /// Clock skew tolerated when checking expiry, in seconds.
+    #[serde(default = "default_token_leeway")]
+    pub leeway: u64,
+}
+
+fn default_token_issuer() -> String {
+    String::from("capture-api")
+}
+
+fn default_token_audience() -> String {
+    String::from("capture-api")
+}
+
+fn default_access_token_ttl() -> u64 {
+    900
+}
+
+fn default_refresh_token_ttl() -> u64 {
+    30 * 24 * 60 * 60
+}
+
+fn default_token_leeway() -> u64 {
+    30
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -25,6 +89,302 @@ pub struct ApiConfiguration {
 pub struct MediaConfiguration {
     pub enabled: bool,
     pub recording_duration: u16,
+    #[serde(default = "default_output_folder")]
+    pub output_folder: PathBuf,
+    /// Path of each recording relative to `output_folder`, see
+    /// `streams::output_path::render_template` for the placeholders.
+    #[serde(default = "default_file_name_template")]
+    pub file_name_template: String,
+    #[serde(default)]
+    pub container: Container,
+    /// Encoding profiles selectable by name when starting a recording.
+    #[serde(default)]
+    pub profiles: HashMap<String, EncodingProfile>,
+    /// Profile of the recordings requesting none, x264enc defaults when unset.
+    pub default_profile: Option<String>,
+    /// Live HLS output of the sources being recorded, disabled when unset.
+    pub hls: Option<HlsConfiguration>,
+    /// MJPEG preview of the sources, disabled when unset.
+    pub preview: Option<PreviewConfiguration>,
+    /// Poster frame and thumbnails of finished recordings, disabled when unset.
+    pub thumbnails: Option<ThumbnailConfiguration>,
+    /// Embedded RTSP server, requires the `rtsp` cargo feature.
+    pub rtsp: Option<RtspConfiguration>,
+    #[serde(default)]
+    pub sources: Vec<SourceConfiguration>,
+}
+
+fn default_output_folder() -> PathBuf {
+    PathBuf::from("output")
+}
+
+fn default_file_name_template() -> String {
+    String::from("{source}/{date}/{id}.{extension}")
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SourceConfiguration {
+    pub name: String,
+    pub video: VideoSource,
+    /// Sound recorded with the video, the recordings are silent when unset.
+    pub audio: Option<AudioConfiguration>,
+    /// Records the source around the clock from startup when set.
+    pub continuous: Option<ContinuousConfiguration>,
+    /// Keeps the last seconds of video encoded in memory, so recordings
+    /// start before they were requested.
+    pub pre_roll: Option<PreRollConfiguration>,
+    /// Watches the source for motion from startup when set.
+    pub motion: Option<MotionConfiguration>,
+}
+
+impl SourceConfiguration {
+    /// Source names end up in RTSP mount paths and API URLs, so they are
+    /// limited to letters, digits, `-` and `_`.
+    fn validate(&self) -> Result<(), ConfigError> {
+        let valid = !self.name.is_empty()
+            && self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        match valid {
+            true => Ok(()),
+            false => Err(ConfigError::Message(format!(
+                "Source name {:?} must only contain letters, digits, - and _",
+                self.name
+            ))),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AudioConfiguration {
+    pub source: AudioSource,
+    #[serde(default)]
+    pub encoder: AudioEncoder,
+    /// Target bitrate in kbit/s, the encoder default when unset.
+    pub bitrate: Option<u32>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ContinuousConfiguration {
+    /// Length of each segment in seconds.
+    pub segment_duration: u64,
+    /// Maximum size of each segment in megabytes.
+    pub segment_size: Option<u64>,
+    /// Encoding profile, `media.default_profile` when unset.
+    pub profile: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct PreRollConfiguration {
+    /// Seconds of video buffered, rounded up to the previous keyframe.
+    pub duration: u64,
+    /// Encoding profile of the buffer and therefore of every recording of
+    /// the source, `media.default_profile` when unset.
+    pub profile: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct MotionConfiguration {
+    /// Luma difference, from 0 to 255, above which a pixel changed.
+    #[serde(default = "default_motion_threshold")]
+    pub threshold: u8,
+    /// Fraction of the watched pixels that must change to detect motion.
+    #[serde(default = "default_motion_min_area")]
+    pub min_area: f64,
+    /// Seconds without motion after which the motion ends.
+    #[serde(default = "default_motion_cooldown")]
+    pub cooldown: u64,
+    /// Parts of the frame watched, the whole frame when empty.
+    #[serde(default)]
+    pub regions: Vec<MotionRegion>,
+    /// Records the source while motion lasts.
+    #[serde(default)]
+    pub record: bool,
+    /// Width of the analysed frames, the height follows the aspect ratio.
+    #[serde(default = "default_motion_width")]
+    pub width: u32,
+    /// Frames analysed per second.
+    #[serde(default = "default_motion_framerate")]
+    pub framerate: u32,
+}
+
+fn default_motion_threshold() -> u8 {
+    25
+}
+
+fn default_motion_min_area() -> f64 {
+    0.02
+}
+
+fn default_motion_cooldown() -> u64 {
+    10
+}
+
+fn default_motion_width() -> u32 {
+    160
+}
+
+fn default_motion_framerate() -> u32 {
+    5
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct HlsConfiguration {
+    /// Folder holding one playlist directory per source.
+    #[serde(default = "default_hls_folder")]
+    pub folder: PathBuf,
+    /// Target duration of each HLS segment in seconds.
+    #[serde(default = "default_hls_target_duration")]
+    pub target_duration: u32,
+    /// Number of segments listed in the playlist.
+    #[serde(default = "default_hls_playlist_length")]
+    pub playlist_length: u32,
+    /// Number of segments kept on disk, older ones are deleted.
+    #[serde(default = "default_hls_max_files")]
+    pub max_files: u32,
+}
+
+fn default_hls_folder() -> PathBuf {
+    PathBuf::from("hls")
+}
+
+fn default_hls_target_duration() -> u32 {
+    2
+}
+
+fn default_hls_playlist_length() -> u32 {
+    5
+}
+
+fn default_hls_max_files() -> u32 {
+    10
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct PreviewConfiguration {
+    #[serde(default = "default_preview_width")]
+    pub width: u32,
+    #[serde(default = "default_preview_height")]
+    pub height: u32,
+    /// Frames per second sent to preview clients.
+    #[serde(default = "default_preview_framerate")]
+    pub framerate: u32,
+    /// JPEG quality, from 0 to 100.
+    #[serde(default = "default_preview_quality")]
+    pub quality: u32,
+}
+
+fn default_preview_width() -> u32 {
+    640
+}
+
+fn default_preview_height() -> u32 {
+    360
+}
+
+fn default_preview_framerate() -> u32 {
+    5
+}
+
+fn default_preview_quality() -> u32 {
+    70
+}
+
+impl PreviewConfiguration {
+    /// Checks the values against the caps and the jpegenc property they
+    /// end up in.
+    fn validate(&self) -> Result<(), ConfigError> {
+        check_range("media.preview.width", self.width, 1..=MAX_CAPS_VALUE)?;
+        check_range("media.preview.height", self.height, 1..=MAX_CAPS_VALUE)?;
+        check_range(
+            "media.preview.framerate",
+            self.framerate,
+            1..=MAX_CAPS_VALUE,
+        )?;
+        check_range("media.preview.quality", self.quality, 0..=100)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ThumbnailConfiguration {
+    /// Number of evenly spaced thumbnails in the strip.
+    #[serde(default = "default_thumbnail_count")]
+    pub count: u32,
+    /// Width of the images, the height follows the aspect ratio.
+    #[serde(default = "default_thumbnail_width")]
+    pub width: u32,
+    /// JPEG quality, from 0 to 100.
+    #[serde(default = "default_thumbnail_quality")]
+    pub quality: u32,
+}
+
+fn default_thumbnail_count() -> u32 {
+    5
+}
+
+fn default_thumbnail_width() -> u32 {
+    320
+}
+
+fn default_thumbnail_quality() -> u32 {
+    80
+}
+
+impl ThumbnailConfiguration {
+    /// Checks the values against the caps and the jpegenc property they
+    /// end up in.
+    fn validate(&self) -> Result<(), ConfigError> {
+        check_range("media.thumbnails.count", self.count, 1..=u32::MAX)?;
+        check_range("media.thumbnails.width", self.width, 1..=MAX_CAPS_VALUE)?;
+        check_range("media.thumbnails.quality", self.quality, 0..=100)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct RtspConfiguration {
+    #[serde(default = "default_rtsp_address")]
+    pub address: String,
+    #[serde(default = "default_rtsp_port")]
+    pub port: u16,
+    /// Host advertised in mount URLs, `address` when unset.
+    pub host: Option<String>,
+    /// Mounts every configured source when the server starts.
+    #[serde(default = "default_rtsp_mount_sources")]
+    pub mount_sources: bool,
+    /// Encoding profile of the mounts, `media.default_profile` when unset.
+    pub profile: Option<String>,
+}
+
+fn default_rtsp_address() -> String {
+    String::from("0.0.0.0")
+}
+
+fn default_rtsp_port() -> u16 {
+    8554
+}
+
+fn default_rtsp_mount_sources() -> bool {
+    true
+}
+
+impl MediaConfiguration {
+    /// Finds an encoding profile by name, defaulting to `default_profile`.
+    /// Without a name nor a default, the encoder defaults are used.
+    pub fn find_profile(&self, name: Option<&str>) -> Option<EncodingProfile> {
+        match name.or(self.default_profile.as_deref()) {
+            Some(name) => self.profiles.get(name).cloned(),
+            None => Some(EncodingProfile::default()),
+        }
+    }
+
+    /// Finds a source by name, defaulting to the first configured source.
+    pub fn find_source(&self, name: Option<&str>) -> Option<&SourceConfiguration> {
+        match name {
+            Some(name) => self.sources.iter().find(|source| source.name == name),
+            None => self.sources.first(),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -61,13 +421,31 @@ impl DataSourceConfiguration {
     }
 }
 
+/// Largest integer a caps field holds.
+const MAX_CAPS_VALUE: u32 = i32::MAX as u32;
+
+/// Fails unless `value` of the setting `key` is within `range`.
+fn check_range(key: &str, value: u32, range: RangeInclusive<u32>) -> Result<(), ConfigError> {
+    match range.contains(&value) {
+        true => Ok(()),
+        false => Err(ConfigError::Message(format!(
+            "{} must be between {} and {}, got {}",
+            key,
+            range.start(),
+            range.end(),
+            value
+        ))),
+    }
+}
+
 pub fn load_config() -> Result<AppConfiguration, ConfigError> {
     dotenv().ok();
 
     let configuration = Config::builder()
         .add_source(File::with_name("configuration/default.toml"))
         // Add in settings from the environment (with a prefix of APP)
-        // Eg.. `APP_DEBUG=1 ./target/app` would set the `debug` key
+        // Eg.. `APP_DEBUG=1 ./target/app` would set the `debug` key and
+        // `APP_AUTH_SECRET=...` the nested `auth.secret` key
         .add_source(Environment::with_prefix("app").separator("_"))
         .build()
         .unwrap();
@@ -75,5 +453,32 @@ pub fn load_config() -> Result<AppConfiguration, ConfigError> {
     // Deserialize the configuration into the AppConfig struct
     let app_config: AppConfiguration = configuration.try_deserialize()?;
 
+    // Fail on startup rather than on the first recording using the profile
+    if app_config.media.enabled {
+        for (name, profile) in &app_config.media.profiles {
+            profile.validate().map_err(|err| {
+                ConfigError::Message(format!("Invalid encoding profile {}: {}", name, err))
+            })?;
+        }
+    }
+    for (index, source) in app_config.media.sources.iter().enumerate() {
+        source.validate()?;
+        if app_config.media.sources[..index]
+            .iter()
+            .any(|other| other.name == source.name)
+        {
+            return Err(ConfigError::Message(format!(
+                "Source name {} is configured twice",
+                source.name
+            )));
+        }
+    }
+    if let Some(preview) = &app_config.media.preview {
+        preview.validate()?;
+    }
+    if let Some(thumbnails) = &app_config.media.thumbnails {
+        thumbnails.validate()?;
+    }
+
     Ok(app_config)
 }
@@ -0,0 +1,54 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::api_key_entity::{ApiKey, ApiKeyScope, CreatedApiKey};
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ApiKeyDto {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<ApiKeyScope>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ApiKeyCreatedDto {
+    #[serde(flatten)]
+    pub api_key: ApiKeyDto,
+    /// Sent in the `X-Api-Key` header, it cannot be read again.
+    pub key: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ApiKeyCreateDto {
+    pub name: String,
+    pub scopes: Vec<ApiKeyScope>,
+    /// The key never expires when unset.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+pub fn get_api_key_dto(api_key: ApiKey) -> ApiKeyDto {
+    ApiKeyDto {
+        id: api_key.id,
+        user_id: api_key.user_id,
+        name: api_key.name,
+        prefix: api_key.prefix,
+        scopes: api_key.scopes,
+        expires_at: api_key.expires_at,
+        last_used_at: api_key.last_used_at,
+        created_at: api_key.created_at,
+    }
+}
+
+pub fn get_api_key_created_dto(created_api_key: CreatedApiKey) -> ApiKeyCreatedDto {
+    ApiKeyCreatedDto {
+        api_key: get_api_key_dto(created_api_key.api_key),
+        key: created_api_key.key,
+    }
+}
@@ -0,0 +1,85 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::features::users::user_entity::Role;
+
+/// What an API key may do, one scope per role level. Scopes do not imply
+/// each other, so a key can trigger snapshots without seeing recordings.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiKeyScope {
+    /// Routes open to viewers.
+    Watch,
+    /// Routes open to operators, recording and snapshots.
+    Operate,
+    /// Routes open to admins, user management.
+    Manage,
+}
+
+impl ApiKeyScope {
+    /// Scope needed for routes requiring `role`.
+    pub fn for_role(role: Role) -> Self {
+        match role {
+            Role::Viewer => ApiKeyScope::Watch,
+            Role::Operator => ApiKeyScope::Operate,
+            Role::Admin => ApiKeyScope::Manage,
+        }
+    }
+
+    /// Role the owner of a key needs for the scope to be of any use.
+    pub fn role(&self) -> Role {
+        match self {
+            ApiKeyScope::Watch => Role::Viewer,
+            ApiKeyScope::Operate => Role::Operator,
+            ApiKeyScope::Manage => Role::Admin,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiKeyScope::Watch => "watch",
+            ApiKeyScope::Operate => "operate",
+            ApiKeyScope::Manage => "manage",
+        }
+    }
+}
+
+impl FromStr for ApiKeyScope {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "watch" => Ok(ApiKeyScope::Watch),
+            "operate" => Ok(ApiKeyScope::Operate),
+            "manage" => Ok(ApiKeyScope::Manage),
+            _ => Err(format!("Unknown API key scope: {}", value)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    /// Start of the key, identifying it in listings and lookups.
+    pub prefix: String,
+    pub scopes: Vec<ApiKeyScope>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Key just created, the only time its secret is known: only its hash is
+/// stored.
+#[derive(Clone, Debug)]
+pub struct CreatedApiKey {
+    pub api_key: ApiKey,
+    pub key: String,
+}
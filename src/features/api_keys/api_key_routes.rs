@@ -0,0 +1,109 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{delete, get},
+    Json, Router,
+};
+use uuid::Uuid;
+
+use crate::{
+    application::ApplicationState,
+    features::{auth::auth_entity::AuthenticatedUser, users::user_entity::Role},
+    service::ServiceType,
+};
+
+use super::{
+    api_key_dto::{get_api_key_created_dto, get_api_key_dto, ApiKeyCreateDto, ApiKeyDto},
+    api_key_service::{ApiKeyService, ApiKeyServiceError},
+};
+
+impl IntoResponse for ApiKeyServiceError {
+    fn into_response(self) -> Response {
+        let status_code = match self {
+            ApiKeyServiceError::ApiKeyNotFound(_) => StatusCode::NOT_FOUND,
+            ApiKeyServiceError::InvalidApiKey => StatusCode::UNAUTHORIZED,
+            ApiKeyServiceError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            ApiKeyServiceError::Forbidden => StatusCode::FORBIDDEN,
+            ApiKeyServiceError::PersistenceDisabled => StatusCode::SERVICE_UNAVAILABLE,
+            ApiKeyServiceError::KeyError(_) | ApiKeyServiceError::DatabaseError(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            ApiKeyServiceError::UserServiceError(err) => return err.into_response(),
+        };
+
+        let body = axum::Json(serde_json::json!({ "error": self.to_string() }));
+        (status_code, body).into_response()
+    }
+}
+
+/// Routes nested under `/api/users/:id/api-keys`.
+pub fn routes(state: ApplicationState) -> Router {
+    let api_key_service = match state.service_provider.get_service("ApiKeyService") {
+        Some(ServiceType::ApiKeyService(api_key_service)) => api_key_service,
+        _ => panic!("ApiKeyService not found in ServiceProvider"),
+    };
+
+    Router::new()
+        .route("/", get(handle_list_api_keys).post(handle_create_api_key))
+        .route("/:key_id", delete(handle_delete_api_key))
+        .with_state(api_key_service)
+}
+
+/// Users manage their own keys and admins those of anyone. Keys cannot
+/// create keys, unless they may manage users anyway.
+fn check_access(user: &AuthenticatedUser, user_id: Uuid) -> Result<(), ApiKeyServiceError> {
+    let is_owner = user.id == user_id && user.scopes.is_none();
+    match is_owner || user.permits(Role::Admin) {
+        true => Ok(()),
+        false => Err(ApiKeyServiceError::Forbidden),
+    }
+}
+
+async fn handle_create_api_key(
+    State(service): State<ApiKeyService>,
+    user: AuthenticatedUser,
+    Path(user_id): Path<Uuid>,
+    Json(api_key_dto): Json<ApiKeyCreateDto>,
+) -> Result<impl IntoResponse, ApiKeyServiceError> {
+    check_access(&user, user_id)?;
+
+    let created_api_key = service
+        .create_api_key(
+            user_id,
+            api_key_dto.name,
+            api_key_dto.scopes,
+            api_key_dto.expires_at,
+        )
+        .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(get_api_key_created_dto(created_api_key)),
+    ))
+}
+
+async fn handle_list_api_keys(
+    State(service): State<ApiKeyService>,
+    user: AuthenticatedUser,
+    Path(user_id): Path<Uuid>,
+) -> Result<impl IntoResponse, ApiKeyServiceError> {
+    check_access(&user, user_id)?;
+
+    let api_keys = service.list_api_keys(user_id).await?;
+    let api_key_dtos: Vec<ApiKeyDto> = api_keys.into_iter().map(get_api_key_dto).collect();
+
+    Ok((StatusCode::OK, Json(api_key_dtos)))
+}
+
+async fn handle_delete_api_key(
+    State(service): State<ApiKeyService>,
+    user: AuthenticatedUser,
+    Path((user_id, key_id)): Path<(Uuid, Uuid)>,
+) -> Result<impl IntoResponse, ApiKeyServiceError> {
+    check_access(&user, user_id)?;
+
+    service.delete_api_key(user_id, key_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
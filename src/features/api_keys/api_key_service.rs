@@ -0,0 +1,217 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    sea_query::Expr, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Set,
+};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::features::users::{
+    user_routes::crypto_utils,
+    user_service::{UserService, UserServiceError},
+};
+use crate::service::Service;
+
+use super::{
+    api_key_entity::{ApiKey, ApiKeyScope, CreatedApiKey},
+    api_key_record::{self, ActiveModel, Entity as ApiKeyRecord},
+};
+
+#[derive(Debug, Error)]
+pub enum ApiKeyServiceError {
+    #[error("API key with id {0} not found")]
+    ApiKeyNotFound(Uuid),
+    #[error("Invalid or expired API key")]
+    InvalidApiKey,
+    #[error("Invalid API key request: {0}")]
+    InvalidRequest(String),
+    #[error("Only admins can manage the API keys of other users")]
+    Forbidden,
+    #[error("API keys cannot be kept without a database")]
+    PersistenceDisabled,
+    #[error("Failed to generate API key: {0}")]
+    KeyError(String),
+    #[error("Database error: {0}")]
+    DatabaseError(String),
+    #[error(transparent)]
+    UserServiceError(#[from] UserServiceError),
+}
+
+/// Keys authenticating machine clients on behalf of a user, hashed at rest
+/// like passwords and found back through their prefix.
+#[derive(Clone)]
+pub struct ApiKeyService {
+    pub name: String,
+    connection: Option<Arc<DatabaseConnection>>,
+    user_service: UserService,
+}
+
+impl Service for ApiKeyService {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl ApiKeyService {
+    pub fn new(connection: Option<Arc<DatabaseConnection>>, user_service: UserService) -> Self {
+        Self {
+            name: String::from("ApiKeyService"),
+            connection,
+            user_service,
+        }
+    }
+
+    /// Creates a key for `user_id`, limited to `scopes` which the role of
+    /// the user must cover.
+    pub async fn create_api_key(
+        &self,
+        user_id: Uuid,
+        name: String,
+        mut scopes: Vec<ApiKeyScope>,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<CreatedApiKey, ApiKeyServiceError> {
+        let connection = self.connection()?;
+        let user = self.user_service.read_user(user_id).await?;
+
+        if scopes.is_empty() {
+            return Err(ApiKeyServiceError::InvalidRequest(String::from(
+                "At least one scope is required",
+            )));
+        }
+        if let Some(scope) = scopes.iter().find(|scope| scope.role() > user.role) {
+            return Err(ApiKeyServiceError::InvalidRequest(format!(
+                "Scope {} requires the {} role",
+                scope.as_str(),
+                scope.role().as_str()
+            )));
+        }
+        let created_at = Utc::now();
+        if expires_at.is_some_and(|expires_at| expires_at <= created_at) {
+            return Err(ApiKeyServiceError::InvalidRequest(String::from(
+                "Expiry must be in the future",
+            )));
+        }
+        scopes.sort();
+        scopes.dedup();
+
+        let prefix = crypto_utils::generate_token(6);
+        let secret = crypto_utils::generate_token(32);
+        let key_hash = crypto_utils::hash_password(&secret)
+            .map_err(|err| ApiKeyServiceError::KeyError(err.to_string()))?;
+
+        let model = ActiveModel {
+            id: Set(Uuid::new_v4()),
+            user_id: Set(user_id),
+            name: Set(name),
+            prefix: Set(prefix.clone()),
+            key_hash: Set(key_hash),
+            scopes: Set(serde_json::to_string(&scopes).unwrap_or_default()),
+            expires_at: Set(expires_at),
+            last_used_at: Set(None),
+            created_at: Set(created_at),
+        };
+        let api_key_record = ApiKeyRecord::insert(model)
+            .exec_with_returning(connection)
+            .await
+            .map_err(|err| ApiKeyServiceError::DatabaseError(err.to_string()))?;
+
+        Ok(CreatedApiKey {
+            api_key: get_api_key_from_record(api_key_record),
+            // The prefix locates the key, the secret proves its ownership
+            key: format!("{}.{}", prefix, secret),
+        })
+    }
+
+    /// Lists the keys of a user, most recent first.
+    pub async fn list_api_keys(&self, user_id: Uuid) -> Result<Vec<ApiKey>, ApiKeyServiceError> {
+        let Some(connection) = &self.connection else {
+            return Ok(Vec::new());
+        };
+
+        let api_key_records = ApiKeyRecord::find()
+            .filter(api_key_record::Column::UserId.eq(user_id))
+            .order_by_desc(api_key_record::Column::CreatedAt)
+            .all(connection.as_ref())
+            .await
+            .map_err(|err| ApiKeyServiceError::DatabaseError(err.to_string()))?;
+
+        Ok(api_key_records
+            .into_iter()
+            .map(get_api_key_from_record)
+            .collect())
+    }
+
+    pub async fn delete_api_key(&self, user_id: Uuid, id: Uuid) -> Result<(), ApiKeyServiceError> {
+        let result = ApiKeyRecord::delete_many()
+            .filter(api_key_record::Column::Id.eq(id))
+            .filter(api_key_record::Column::UserId.eq(user_id))
+            .exec(self.connection()?)
+            .await
+            .map_err(|err| ApiKeyServiceError::DatabaseError(err.to_string()))?;
+
+        match result.rows_affected {
+            0 => Err(ApiKeyServiceError::ApiKeyNotFound(id)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Finds the key matching `key`, recording that it was used.
+    pub async fn verify_api_key(&self, key: &str) -> Result<ApiKey, ApiKeyServiceError> {
+        let connection = self.connection()?;
+        let (prefix, secret) = key
+            .split_once('.')
+            .ok_or(ApiKeyServiceError::InvalidApiKey)?;
+
+        let api_key_record = ApiKeyRecord::find()
+            .filter(api_key_record::Column::Prefix.eq(prefix))
+            .one(connection)
+            .await
+            .map_err(|err| ApiKeyServiceError::DatabaseError(err.to_string()))?
+            .ok_or(ApiKeyServiceError::InvalidApiKey)?;
+
+        let now = Utc::now();
+        let is_expired = api_key_record
+            .expires_at
+            .is_some_and(|expires_at| expires_at <= now);
+        if is_expired || !crypto_utils::verify_password(secret, &api_key_record.key_hash) {
+            return Err(ApiKeyServiceError::InvalidApiKey);
+        }
+
+        ApiKeyRecord::update_many()
+            .col_expr(api_key_record::Column::LastUsedAt, Expr::value(now))
+            .filter(api_key_record::Column::Id.eq(api_key_record.id))
+            .exec(connection)
+            .await
+            .map_err(|err| ApiKeyServiceError::DatabaseError(err.to_string()))?;
+
+        Ok(ApiKey {
+            last_used_at: Some(now),
+            ..get_api_key_from_record(api_key_record)
+        })
+    }
+
+    fn connection(&self) -> Result<&DatabaseConnection, ApiKeyServiceError> {
+        self.connection
+            .as_deref()
+            .ok_or(ApiKeyServiceError::PersistenceDisabled)
+    }
+}
+
+fn get_api_key_from_record(record: api_key_record::Model) -> ApiKey {
+    ApiKey {
+        id: record.id,
+        user_id: record.user_id,
+        name: record.name,
+        prefix: record.prefix,
+        // Unknown scopes grant nothing
+        scopes: serde_json::from_str::<Vec<String>>(&record.scopes)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|scope| scope.parse().ok())
+            .collect(),
+        expires_at: record.expires_at,
+        last_used_at: record.last_used_at,
+        created_at: record.created_at,
+    }
+}
@@ -0,0 +1,5 @@
+pub mod api_key_dto;
+pub mod api_key_entity;
+pub mod api_key_record;
+pub mod api_key_routes;
+pub mod api_key_service;
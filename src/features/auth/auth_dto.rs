@@ -0,0 +1,53 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::auth_entity::{AccessToken, TokenPair};
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct LoginDto {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct RefreshTokenDto {
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct TokenPairDto {
+    pub access_token: String,
+    /// Always `Bearer`.
+    pub token_type: String,
+    /// Seconds until the access token expires.
+    pub expires_in: u64,
+    /// Single use, a new one comes with each refresh.
+    pub refresh_token: String,
+    /// Seconds until the refresh token expires.
+    pub refresh_expires_in: u64,
+}
+
+pub fn get_token_pair_dto(tokens: TokenPair) -> TokenPairDto {
+    TokenPairDto {
+        access_token: tokens.access_token.token,
+        token_type: String::from("Bearer"),
+        expires_in: tokens.access_token.expires_in,
+        refresh_token: tokens.refresh_token.token,
+        refresh_expires_in: tokens.refresh_token.expires_in,
+    }
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct PreviewTokenDto {
+    /// Passed as the `token` query parameter of the preview.
+    pub token: String,
+    /// Seconds left to request the preview, which then streams until closed.
+    pub expires_in: u64,
+}
+
+pub fn get_preview_token_dto(token: AccessToken) -> PreviewTokenDto {
+    PreviewTokenDto {
+        token: token.token,
+        expires_in: token.expires_in,
+    }
+}
@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::features::{api_keys::api_key_entity::ApiKeyScope, users::user_entity::Role};
+
+/// Claims of an access token.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Claims {
+    /// Id of the authenticated user.
+    pub sub: Uuid,
+    /// Role of the user when the token was issued.
+    pub role: Role,
+    pub iss: String,
+    pub aud: String,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+/// Claims of a preview token, which only grants watching the preview of
+/// `source`. Its audience differs from the one of the access tokens.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PreviewClaims {
+    pub sub: Uuid,
+    pub role: Role,
+    pub source: String,
+    pub iss: String,
+    pub aud: String,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+/// User a request was authenticated as, see `auth_middleware`.
+#[derive(Clone, Debug)]
+pub struct AuthenticatedUser {
+    pub id: Uuid,
+    pub role: Role,
+    /// Scopes of the API key or preview token the request authenticated
+    /// with, `None` for access tokens.
+    pub scopes: Option<Vec<ApiKeyScope>>,
+}
+
+impl AuthenticatedUser {
+    /// Whether the user has `role`, and their API key, if any, its scope.
+    pub fn permits(&self, role: Role) -> bool {
+        self.role >= role
+            && self
+                .scopes
+                .as_ref()
+                .map_or(true, |scopes| scopes.contains(&ApiKeyScope::for_role(role)))
+    }
+}
+
+/// Signed access token handed to a client after it authenticated.
+#[derive(Clone, Debug)]
+pub struct AccessToken {
+    pub token: String,
+    /// Lifetime of the token in seconds.
+    pub expires_in: u64,
+}
+
+/// Opaque token of a session, exchanged for new tokens once the access
+/// token expired.
+#[derive(Clone, Debug)]
+pub struct RefreshToken {
+    pub token: String,
+    /// Lifetime of the token in seconds.
+    pub expires_in: u64,
+}
+
+/// Tokens handed to a client on login and on each refresh.
+#[derive(Clone, Debug)]
+pub struct TokenPair {
+    pub access_token: AccessToken,
+    pub refresh_token: RefreshToken,
+}
@@ -0,0 +1,145 @@
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Path, Query, Request, State},
+    http::{header, request::Parts},
+    middleware::Next,
+    response::Response,
+};
+use serde::Deserialize;
+
+use crate::features::users::user_entity::Role;
+
+use super::{
+    auth_entity::AuthenticatedUser,
+    auth_service::{AuthService, AuthServiceError},
+};
+
+/// Header carrying the API key of machine clients.
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Why the bearer token of a request was refused, reported by the
+/// `AuthenticatedUser` extractor.
+#[derive(Clone, Debug)]
+enum TokenRejection {
+    Expired,
+    Invalid(String),
+}
+
+impl From<AuthServiceError> for TokenRejection {
+    fn from(err: AuthServiceError) -> Self {
+        match err {
+            AuthServiceError::TokenExpired => TokenRejection::Expired,
+            AuthServiceError::InvalidToken(message) => TokenRejection::Invalid(message),
+            err => TokenRejection::Invalid(err.to_string()),
+        }
+    }
+}
+
+/// Validates the API key or the bearer token of a request when it carries
+/// one, making the user available to the `AuthenticatedUser` extractor.
+/// Requests with an invalid API key are rejected. Those with an invalid or
+/// expired bearer token go through as anonymous, so that they can still log
+/// in or refresh, and routes requiring a user reject them with the reason.
+pub async fn authenticate(
+    State(service): State<AuthService>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, AuthServiceError> {
+    let api_key = request
+        .headers()
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned);
+    if let Some(api_key) = api_key {
+        let user = service.validate_api_key(&api_key).await?;
+        request.extensions_mut().insert(user);
+        return Ok(next.run(request).await);
+    }
+
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| {
+            value
+                .split_once(' ')
+                .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+                .map(|(_, token)| token.trim())
+                .ok_or_else(|| AuthServiceError::InvalidToken(String::from("Not a bearer token")))
+        });
+
+    match token.map(|token| service.validate_access_token(token?)) {
+        Some(Ok(user)) => {
+            request.extensions_mut().insert(user);
+        }
+        Some(Err(err)) => {
+            request.extensions_mut().insert(TokenRejection::from(err));
+        }
+        None => (),
+    }
+
+    Ok(next.run(request).await)
+}
+
+/// Query of the routes accepting a preview token.
+#[derive(Debug, Deserialize)]
+pub struct PreviewTokenQuery {
+    token: Option<String>,
+}
+
+/// Authenticates requests for the preview of the `:source` of their path
+/// with the preview token of their `token` query parameter, for clients
+/// such as `<img>` that cannot send headers. Requests already authenticated
+/// go through.
+pub async fn authenticate_preview(
+    State(service): State<AuthService>,
+    Path(source): Path<String>,
+    Query(query): Query<PreviewTokenQuery>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, AuthServiceError> {
+    let authenticated = request.extensions().get::<AuthenticatedUser>().is_some();
+    if let (false, Some(token)) = (authenticated, query.token) {
+        let user = service.validate_preview_token(&token, &source)?;
+        request.extensions_mut().insert(user);
+    }
+
+    Ok(next.run(request).await)
+}
+
+/// Rejects users whose role does not include the `role` the layer was
+/// given as state, or whose API key lacks its scope.
+pub async fn require_role(
+    State(role): State<Role>,
+    user: AuthenticatedUser,
+    request: Request,
+    next: Next,
+) -> Result<Response, AuthServiceError> {
+    if !user.permits(role) {
+        return Err(AuthServiceError::Forbidden(role));
+    }
+
+    Ok(next.run(request).await)
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AuthenticatedUser
+where
+    S: Send + Sync,
+{
+    type Rejection = AuthServiceError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(user) = parts.extensions.get::<AuthenticatedUser>() {
+            return Ok(user.clone());
+        }
+
+        match parts.extensions.get::<TokenRejection>() {
+            Some(TokenRejection::Expired) => Err(AuthServiceError::TokenExpired),
+            Some(TokenRejection::Invalid(message)) => {
+                Err(AuthServiceError::InvalidToken(message.clone()))
+            }
+            None => Err(AuthServiceError::MissingToken),
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+
+use crate::{application::ApplicationState, service::ServiceType};
+
+use super::{
+    auth_dto::{get_token_pair_dto, LoginDto, RefreshTokenDto},
+    auth_entity::AuthenticatedUser,
+    auth_service::{AuthService, AuthServiceError},
+};
+
+impl IntoResponse for AuthServiceError {
+    fn into_response(self) -> Response {
+        let status_code = match self {
+            AuthServiceError::InvalidCredentials
+            | AuthServiceError::InvalidRefreshToken
+            | AuthServiceError::RefreshTokenReused => StatusCode::UNAUTHORIZED,
+            AuthServiceError::MissingToken
+            | AuthServiceError::InvalidToken(_)
+            | AuthServiceError::TokenExpired => {
+                let body = axum::Json(serde_json::json!({ "error": self.to_string() }));
+                return (
+                    StatusCode::UNAUTHORIZED,
+                    [(header::WWW_AUTHENTICATE, "Bearer")],
+                    body,
+                )
+                    .into_response();
+            }
+            AuthServiceError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AuthServiceError::PersistenceDisabled => StatusCode::SERVICE_UNAVAILABLE,
+            AuthServiceError::TokenError(_)
+            | AuthServiceError::ConfigurationError(_)
+            | AuthServiceError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AuthServiceError::UserServiceError(err) => return err.into_response(),
+            AuthServiceError::ApiKeyServiceError(err) => return err.into_response(),
+        };
+
+        let body = axum::Json(serde_json::json!({ "error": self.to_string() }));
+        (status_code, body).into_response()
+    }
+}
+
+pub fn routes(state: ApplicationState) -> Router {
+    let auth_service = match state.service_provider.get_service("AuthService") {
+        Some(ServiceType::AuthService(auth_service)) => auth_service,
+        _ => panic!("AuthService not found in ServiceProvider"),
+    };
+
+    Router::new()
+        .route("/login", post(handle_login))
+        .route("/refresh", post(handle_refresh))
+        .route("/logout", post(handle_logout))
+        .route("/logout-all", post(handle_logout_all))
+        .with_state(auth_service)
+}
+
+async fn handle_login(
+    State(service): State<AuthService>,
+    Json(login_dto): Json<LoginDto>,
+) -> Result<impl IntoResponse, AuthServiceError> {
+    let tokens = service
+        .login(&login_dto.username, &login_dto.password)
+        .await?;
+
+    Ok((StatusCode::OK, Json(get_token_pair_dto(tokens))))
+}
+
+async fn handle_refresh(
+    State(service): State<AuthService>,
+    Json(refresh_token_dto): Json<RefreshTokenDto>,
+) -> Result<impl IntoResponse, AuthServiceError> {
+    let tokens = service.refresh(&refresh_token_dto.refresh_token).await?;
+
+    Ok((StatusCode::OK, Json(get_token_pair_dto(tokens))))
+}
+
+async fn handle_logout(
+    State(service): State<AuthService>,
+    Json(refresh_token_dto): Json<RefreshTokenDto>,
+) -> Result<impl IntoResponse, AuthServiceError> {
+    service.logout(&refresh_token_dto.refresh_token).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn handle_logout_all(
+    State(service): State<AuthService>,
+    user: AuthenticatedUser,
+) -> Result<impl IntoResponse, AuthServiceError> {
+    service.logout_all(user.id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,531 @@
+use std::sync::Arc;
+
+use chrono::{Duration, Utc};
+use jsonwebtoken::{errors::ErrorKind, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use sea_orm::{
+    sea_query::{Expr, SimpleExpr},
+    ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set,
+};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::configuration::AuthConfiguration;
+use crate::features::api_keys::{
+    api_key_entity::ApiKeyScope,
+    api_key_service::{ApiKeyService, ApiKeyServiceError},
+};
+use crate::features::users::{
+    user_entity::{Role, User},
+    user_routes::crypto_utils,
+    user_service::{UserService, UserServiceError},
+};
+use crate::service::Service;
+
+use super::{
+    auth_entity::{AccessToken, AuthenticatedUser, Claims, PreviewClaims, RefreshToken, TokenPair},
+    session_record::{self, ActiveModel, Entity as SessionRecord},
+};
+
+/// Shortest HMAC secret accepted, the output size of SHA-256.
+const MIN_SECRET_LENGTH: usize = 32;
+
+/// Secret of the sample configurations, refused so it never reaches
+/// production.
+const PLACEHOLDER_SECRET: &str = "change-me";
+
+/// Lifetime of the preview tokens in seconds, they are only checked when the
+/// preview is requested.
+const PREVIEW_TOKEN_TTL: u64 = 60;
+
+#[derive(Debug, Error)]
+pub enum AuthServiceError {
+    #[error("Invalid username or password")]
+    InvalidCredentials,
+    #[error("Missing bearer token")]
+    MissingToken,
+    #[error("Invalid token: {0}")]
+    InvalidToken(String),
+    #[error("Token expired")]
+    TokenExpired,
+    #[error("Insufficient role or API key scope, {} required", .0.as_str())]
+    Forbidden(Role),
+    #[error("Invalid or expired refresh token")]
+    InvalidRefreshToken,
+    #[error("Refresh token already used, the session was revoked")]
+    RefreshTokenReused,
+    #[error("Sessions cannot be kept without a database")]
+    PersistenceDisabled,
+    #[error("Database error: {0}")]
+    DatabaseError(String),
+    #[error("Failed to issue token: {0}")]
+    TokenError(String),
+    #[error("Invalid auth configuration: {0}")]
+    ConfigurationError(String),
+    #[error(transparent)]
+    UserServiceError(#[from] UserServiceError),
+    #[error(transparent)]
+    ApiKeyServiceError(#[from] ApiKeyServiceError),
+}
+
+/// Authenticates users, issues their access tokens and validates them.
+/// Each login opens a session whose refresh token renews the access token.
+#[derive(Clone)]
+pub struct AuthService {
+    pub name: String,
+    connection: Option<Arc<DatabaseConnection>>,
+    configuration: Arc<AuthConfiguration>,
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    validation: Validation,
+    user_service: UserService,
+    api_key_service: ApiKeyService,
+}
+
+impl Service for AuthService {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl AuthService {
+    /// Fails when the keys of the configured algorithm are missing or
+    /// cannot be read.
+    pub fn new(
+        connection: Option<Arc<DatabaseConnection>>,
+        configuration: AuthConfiguration,
+        user_service: UserService,
+        api_key_service: ApiKeyService,
+    ) -> Result<Self, AuthServiceError> {
+        let (encoding_key, decoding_key) = load_keys(&configuration)?;
+
+        let mut validation = Validation::new(configuration.algorithm);
+        validation.set_issuer(&[&configuration.issuer]);
+        validation.set_audience(&[&configuration.audience]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        validation.leeway = configuration.leeway;
+
+        Ok(Self {
+            name: String::from("AuthService"),
+            connection,
+            configuration: Arc::new(configuration),
+            encoding_key,
+            decoding_key,
+            validation,
+            user_service,
+            api_key_service,
+        })
+    }
+
+    /// Checks the password of `username`, opening a session for the user
+    /// when it matches.
+    pub async fn login(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<TokenPair, AuthServiceError> {
+        let user = self.user_service.find_user_by_username(username).await?;
+
+        // Unknown users are checked against a dummy hash, so the response
+        // time does not reveal which usernames exist
+        let password_hash = match &user {
+            Some(user) => user.password_hash.as_str(),
+            None => crypto_utils::dummy_password_hash(),
+        };
+        let verified = crypto_utils::verify_password(password, password_hash);
+
+        match user {
+            Some(user) if verified => {
+                self.delete_expired_sessions(user.id).await?;
+                self.open_session(&user, Uuid::new_v4()).await
+            }
+            _ => Err(AuthServiceError::InvalidCredentials),
+        }
+    }
+
+    /// Exchanges a refresh token for new tokens. Each refresh token is
+    /// single use: presenting one again means it leaked, so every session
+    /// rotated from the same login is revoked.
+    pub async fn refresh(&self, refresh_token: &str) -> Result<TokenPair, AuthServiceError> {
+        let connection = self.connection()?;
+        let session = self.find_session(refresh_token).await?;
+        let now = Utc::now();
+        if session.revoked_at.is_some() || session.expires_at <= now {
+            return Err(AuthServiceError::InvalidRefreshToken);
+        }
+
+        // Only marks an unused token, so that concurrent refreshes with the
+        // same token cannot both succeed
+        let result = SessionRecord::update_many()
+            .col_expr(session_record::Column::UsedAt, Expr::value(now))
+            .filter(session_record::Column::Id.eq(session.id))
+            .filter(session_record::Column::UsedAt.is_null())
+            .exec(connection)
+            .await
+            .map_err(|err| AuthServiceError::DatabaseError(err.to_string()))?;
+        if result.rows_affected == 0 {
+            tracing::warn!(
+                "Refresh token of session {} reused, revoking its family",
+                session.id
+            );
+            self.revoke_sessions(session_record::Column::FamilyId.eq(session.family_id))
+                .await?;
+            return Err(AuthServiceError::RefreshTokenReused);
+        }
+
+        // The role may have changed since the previous tokens
+        let user = self.user_service.read_user(session.user_id).await?;
+        self.open_session(&user, session.family_id).await
+    }
+
+    /// Ends the session of a refresh token.
+    pub async fn logout(&self, refresh_token: &str) -> Result<(), AuthServiceError> {
+        let session = self.find_session(refresh_token).await?;
+
+        self.revoke_sessions(session_record::Column::FamilyId.eq(session.family_id))
+            .await
+    }
+
+    /// Ends every session of a user, on all their devices. Access tokens
+    /// already issued stay valid until they expire.
+    pub async fn logout_all(&self, user_id: Uuid) -> Result<(), AuthServiceError> {
+        self.revoke_sessions(session_record::Column::UserId.eq(user_id))
+            .await
+    }
+
+    /// Checks the signature, issuer, audience and expiry of an access token.
+    pub fn validate_access_token(
+        &self,
+        token: &str,
+    ) -> Result<AuthenticatedUser, AuthServiceError> {
+        let token_data =
+            jsonwebtoken::decode::<Claims>(token, &self.decoding_key, &self.validation).map_err(
+                |err| match err.kind() {
+                    ErrorKind::ExpiredSignature => AuthServiceError::TokenExpired,
+                    _ => AuthServiceError::InvalidToken(err.to_string()),
+                },
+            )?;
+
+        Ok(AuthenticatedUser {
+            id: token_data.claims.sub,
+            role: token_data.claims.role,
+            scopes: None,
+        })
+    }
+
+    /// Issues a short-lived token letting `user` watch the preview of
+    /// `source` from clients that cannot send headers, such as `<img>`.
+    pub fn issue_preview_token(
+        &self,
+        user: &AuthenticatedUser,
+        source: &str,
+    ) -> Result<AccessToken, AuthServiceError> {
+        let issued_at = Utc::now().timestamp();
+        let claims = PreviewClaims {
+            sub: user.id,
+            role: user.role,
+            source: source.to_owned(),
+            iss: self.configuration.issuer.clone(),
+            aud: self.preview_audience(),
+            iat: issued_at,
+            exp: issued_at + PREVIEW_TOKEN_TTL as i64,
+        };
+
+        let header = Header::new(self.configuration.algorithm);
+        let token = jsonwebtoken::encode(&header, &claims, &self.encoding_key)
+            .map_err(|err| AuthServiceError::TokenError(err.to_string()))?;
+
+        Ok(AccessToken {
+            token,
+            expires_in: PREVIEW_TOKEN_TTL,
+        })
+    }
+
+    /// Checks a preview token for the preview of `source`. The user it
+    /// authenticates as can only watch.
+    pub fn validate_preview_token(
+        &self,
+        token: &str,
+        source: &str,
+    ) -> Result<AuthenticatedUser, AuthServiceError> {
+        let mut validation = self.validation.clone();
+        validation.set_audience(&[self.preview_audience()]);
+
+        let token_data =
+            jsonwebtoken::decode::<PreviewClaims>(token, &self.decoding_key, &validation).map_err(
+                |err| match err.kind() {
+                    ErrorKind::ExpiredSignature => AuthServiceError::TokenExpired,
+                    _ => AuthServiceError::InvalidToken(err.to_string()),
+                },
+            )?;
+        if token_data.claims.source != source {
+            return Err(AuthServiceError::InvalidToken(format!(
+                "Preview token of another source than {}",
+                source
+            )));
+        }
+
+        Ok(AuthenticatedUser {
+            id: token_data.claims.sub,
+            role: token_data.claims.role,
+            scopes: Some(vec![ApiKeyScope::Watch]),
+        })
+    }
+
+    /// Checks an API key, authenticating as its owner with their current
+    /// role.
+    pub async fn validate_api_key(&self, key: &str) -> Result<AuthenticatedUser, AuthServiceError> {
+        let api_key = self.api_key_service.verify_api_key(key).await?;
+        let user = self.user_service.read_user(api_key.user_id).await?;
+
+        Ok(AuthenticatedUser {
+            id: user.id,
+            role: user.role,
+            scopes: Some(api_key.scopes),
+        })
+    }
+
+    /// Audience of the preview tokens, so that they are refused as access
+    /// tokens and the other way around.
+    fn preview_audience(&self) -> String {
+        format!("{}/preview", self.configuration.audience)
+    }
+
+    fn connection(&self) -> Result<&DatabaseConnection, AuthServiceError> {
+        self.connection
+            .as_deref()
+            .ok_or(AuthServiceError::PersistenceDisabled)
+    }
+
+    /// Starts a session of `family_id`, the login it was rotated from.
+    async fn open_session(
+        &self,
+        user: &User,
+        family_id: Uuid,
+    ) -> Result<TokenPair, AuthServiceError> {
+        let connection = self.connection()?;
+        let access_token = self.issue_access_token(user)?;
+
+        let id = Uuid::new_v4();
+        let secret = crypto_utils::generate_token(32);
+        let token_hash = crypto_utils::hash_password(&secret)
+            .map_err(|err| AuthServiceError::TokenError(err.to_string()))?;
+        let created_at = Utc::now();
+        let expires_in = self.configuration.refresh_token_ttl;
+
+        let model = ActiveModel {
+            id: Set(id),
+            user_id: Set(user.id),
+            family_id: Set(family_id),
+            token_hash: Set(token_hash),
+            created_at: Set(created_at),
+            expires_at: Set(created_at + Duration::seconds(expires_in as i64)),
+            used_at: Set(None),
+            revoked_at: Set(None),
+        };
+        SessionRecord::insert(model)
+            .exec(connection)
+            .await
+            .map_err(|err| AuthServiceError::DatabaseError(err.to_string()))?;
+
+        Ok(TokenPair {
+            access_token,
+            // The id locates the session, the secret proves its ownership
+            refresh_token: RefreshToken {
+                token: format!("{}.{}", id, secret),
+                expires_in,
+            },
+        })
+    }
+
+    async fn find_session(
+        &self,
+        refresh_token: &str,
+    ) -> Result<session_record::Model, AuthServiceError> {
+        let connection = self.connection()?;
+        let (id, secret) = refresh_token
+            .split_once('.')
+            .and_then(|(id, secret)| Some((Uuid::parse_str(id).ok()?, secret)))
+            .ok_or(AuthServiceError::InvalidRefreshToken)?;
+
+        let session = SessionRecord::find_by_id(id)
+            .one(connection)
+            .await
+            .map_err(|err| AuthServiceError::DatabaseError(err.to_string()))?
+            .ok_or(AuthServiceError::InvalidRefreshToken)?;
+
+        match crypto_utils::verify_password(secret, &session.token_hash) {
+            true => Ok(session),
+            false => Err(AuthServiceError::InvalidRefreshToken),
+        }
+    }
+
+    async fn revoke_sessions(&self, condition: SimpleExpr) -> Result<(), AuthServiceError> {
+        SessionRecord::update_many()
+            .col_expr(session_record::Column::RevokedAt, Expr::value(Utc::now()))
+            .filter(condition)
+            .filter(session_record::Column::RevokedAt.is_null())
+            .exec(self.connection()?)
+            .await
+            .map_err(|err| AuthServiceError::DatabaseError(err.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Rotated sessions are kept until they expire to detect reuse, they
+    /// are cleaned up on the next login of their user.
+    async fn delete_expired_sessions(&self, user_id: Uuid) -> Result<(), AuthServiceError> {
+        SessionRecord::delete_many()
+            .filter(session_record::Column::UserId.eq(user_id))
+            .filter(session_record::Column::ExpiresAt.lt(Utc::now()))
+            .exec(self.connection()?)
+            .await
+            .map_err(|err| AuthServiceError::DatabaseError(err.to_string()))?;
+
+        Ok(())
+    }
+
+    fn issue_access_token(&self, user: &User) -> Result<AccessToken, AuthServiceError> {
+        let issued_at = Utc::now().timestamp();
+        let expires_in = self.configuration.access_token_ttl;
+        let claims = Claims {
+            sub: user.id,
+            role: user.role,
+            iss: self.configuration.issuer.clone(),
+            aud: self.configuration.audience.clone(),
+            iat: issued_at,
+            exp: issued_at + expires_in as i64,
+        };
+
+        let header = Header::new(self.configuration.algorithm);
+        let token = jsonwebtoken::encode(&header, &claims, &self.encoding_key)
+            .map_err(|err| AuthServiceError::TokenError(err.to_string()))?;
+
+        Ok(AccessToken { token, expires_in })
+    }
+}
+
+/// Keys signing and validating tokens with the configured algorithm, the
+/// secret for HMAC and the PEM key pair otherwise.
+fn load_keys(
+    configuration: &AuthConfiguration,
+) -> Result<(EncodingKey, DecodingKey), AuthServiceError> {
+    let algorithm = configuration.algorithm;
+    if matches!(
+        algorithm,
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+    ) {
+        let secret = configuration.secret.as_ref().ok_or_else(|| {
+            AuthServiceError::ConfigurationError(format!("{:?} requires a secret", algorithm))
+        })?;
+        // Short secrets are brute forced offline from any issued token
+        if secret.is_empty() || secret == PLACEHOLDER_SECRET || secret.len() < MIN_SECRET_LENGTH {
+            return Err(AuthServiceError::ConfigurationError(format!(
+                "{:?} requires a random secret of at least {} bytes",
+                algorithm, MIN_SECRET_LENGTH
+            )));
+        }
+
+        return Ok((
+            EncodingKey::from_secret(secret.as_bytes()),
+            DecodingKey::from_secret(secret.as_bytes()),
+        ));
+    }
+
+    let read_key = |path: Option<&std::path::PathBuf>, name: &str| {
+        let path = path.ok_or_else(|| {
+            AuthServiceError::ConfigurationError(format!("{:?} requires a {}", algorithm, name))
+        })?;
+        std::fs::read(path).map_err(|err| {
+            AuthServiceError::ConfigurationError(format!("{}: {}", path.display(), err))
+        })
+    };
+    let private_key = read_key(configuration.private_key.as_ref(), "private_key")?;
+    let public_key = read_key(configuration.public_key.as_ref(), "public_key")?;
+
+    let keys = match algorithm {
+        Algorithm::ES256 | Algorithm::ES384 => (
+            EncodingKey::from_ec_pem(&private_key),
+            DecodingKey::from_ec_pem(&public_key),
+        ),
+        Algorithm::EdDSA => (
+            EncodingKey::from_ed_pem(&private_key),
+            DecodingKey::from_ed_pem(&public_key),
+        ),
+        _ => (
+            EncodingKey::from_rsa_pem(&private_key),
+            DecodingKey::from_rsa_pem(&public_key),
+        ),
+    };
+    match keys {
+        (Ok(encoding_key), Ok(decoding_key)) => Ok((encoding_key, decoding_key)),
+        (Err(err), _) | (_, Err(err)) => Err(AuthServiceError::ConfigurationError(err.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonwebtoken::Algorithm;
+    use uuid::Uuid;
+
+    use crate::configuration::AuthConfiguration;
+    use crate::features::api_keys::api_key_service::ApiKeyService;
+    use crate::features::auth::auth_entity::AuthenticatedUser;
+    use crate::features::users::{
+        user_entity::{Role, User},
+        user_service::UserService,
+    };
+
+    use super::AuthService;
+
+    fn auth_service() -> AuthService {
+        let configuration = AuthConfiguration {
+            algorithm: Algorithm::HS256,
+            secret: Some(String::from("0123456789abcdef0123456789abcdef")),
+            private_key: None,
+            public_key: None,
+            issuer: String::from("capture-api"),
+            audience: String::from("capture-api"),
+            access_token_ttl: 900,
+            refresh_token_ttl: 3600,
+            leeway: 0,
+        };
+        let user_service = UserService::new(None);
+        let api_key_service = ApiKeyService::new(None, user_service.clone());
+
+        AuthService::new(None, configuration, user_service, api_key_service).unwrap()
+    }
+
+    #[test]
+    fn it_should_limit_preview_tokens_to_watching_their_source() {
+        let service = auth_service();
+        let user = AuthenticatedUser {
+            id: Uuid::new_v4(),
+            role: Role::Admin,
+            scopes: None,
+        };
+        let token = service.issue_preview_token(&user, "front").unwrap().token;
+
+        let preview_user = service.validate_preview_token(&token, "front").unwrap();
+        assert_eq!(preview_user.id, user.id);
+        assert!(preview_user.permits(Role::Viewer));
+        assert!(!preview_user.permits(Role::Operator));
+
+        assert!(service.validate_preview_token(&token, "back").is_err());
+        assert!(service.validate_access_token(&token).is_err());
+    }
+
+    #[test]
+    fn it_should_refuse_access_tokens_as_preview_tokens() {
+        let service = auth_service();
+        let user = User {
+            id: Uuid::new_v4(),
+            username: String::from("viewer"),
+            password_hash: String::new(),
+            role: Role::Viewer,
+        };
+        let token = service.issue_access_token(&user).unwrap().token;
+
+        assert!(service.validate_access_token(&token).is_ok());
+        assert!(service.validate_preview_token(&token, "front").is_err());
+    }
+}
@@ -0,0 +1,6 @@
+pub mod auth_dto;
+pub mod auth_entity;
+pub mod auth_middleware;
+pub mod auth_routes;
+pub mod auth_service;
+pub mod session_record;
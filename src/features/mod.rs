@@ -1,2 +1,6 @@
+pub mod api_keys;
+pub mod auth;
+pub mod recordings;
+pub mod snapshots;
 pub mod streams;
 pub mod users;
@@ -0,0 +1,113 @@
+/// Part of a file selected by a `Range` header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ByteRange {
+    /// No usable range, the whole file is sent.
+    Full,
+    /// Bytes from `start` to `end`, both included.
+    Partial { start: u64, end: u64 },
+    /// The range starts past the end of the file.
+    Unsatisfiable,
+}
+
+impl ByteRange {
+    /// Resolves a `Range` header such as `bytes=0-499`, `bytes=500-` or
+    /// `bytes=-500` against a file of `length` bytes. Only single ranges are
+    /// honored, multiple or malformed ranges select the whole file as allowed
+    /// by RFC 9110.
+    pub fn parse(header: &str, length: u64) -> Self {
+        let Some((start, end)) = header
+            .trim()
+            .strip_prefix("bytes=")
+            .filter(|ranges| !ranges.contains(','))
+            .and_then(|range| range.split_once('-'))
+        else {
+            return ByteRange::Full;
+        };
+        let (start, end) = (start.trim(), end.trim());
+
+        if start.is_empty() {
+            // Suffix range, the last `end` bytes
+            return match end.parse::<u64>() {
+                Ok(0) => ByteRange::Unsatisfiable,
+                Ok(_) if length == 0 => ByteRange::Unsatisfiable,
+                Ok(suffix) => ByteRange::Partial {
+                    start: length.saturating_sub(suffix),
+                    end: length - 1,
+                },
+                Err(_) => ByteRange::Full,
+            };
+        }
+
+        let Ok(start) = start.parse::<u64>() else {
+            return ByteRange::Full;
+        };
+        let end = match end {
+            "" => u64::MAX,
+            end => match end.parse::<u64>() {
+                Ok(end) if end >= start => end,
+                _ => return ByteRange::Full,
+            },
+        };
+
+        if start >= length {
+            return ByteRange::Unsatisfiable;
+        }
+
+        ByteRange::Partial {
+            start,
+            end: end.min(length - 1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_resolve_single_ranges() {
+        assert_eq!(
+            ByteRange::parse("bytes=0-499", 1000),
+            ByteRange::Partial { start: 0, end: 499 }
+        );
+        assert_eq!(
+            ByteRange::parse("bytes=500-", 1000),
+            ByteRange::Partial {
+                start: 500,
+                end: 999
+            }
+        );
+        assert_eq!(
+            ByteRange::parse("bytes=-200", 1000),
+            ByteRange::Partial {
+                start: 800,
+                end: 999
+            }
+        );
+        assert_eq!(
+            ByteRange::parse("bytes=900-5000", 1000),
+            ByteRange::Partial {
+                start: 900,
+                end: 999
+            }
+        );
+    }
+
+    #[test]
+    fn it_should_reject_ranges_past_the_end() {
+        assert_eq!(
+            ByteRange::parse("bytes=1000-", 1000),
+            ByteRange::Unsatisfiable
+        );
+        assert_eq!(ByteRange::parse("bytes=-0", 1000), ByteRange::Unsatisfiable);
+        assert_eq!(ByteRange::parse("bytes=-10", 0), ByteRange::Unsatisfiable);
+    }
+
+    #[test]
+    fn it_should_ignore_unsupported_ranges() {
+        assert_eq!(ByteRange::parse("bytes=0-1,5-9", 1000), ByteRange::Full);
+        assert_eq!(ByteRange::parse("bytes=500-100", 1000), ByteRange::Full);
+        assert_eq!(ByteRange::parse("items=0-9", 1000), ByteRange::Full);
+        assert_eq!(ByteRange::parse("bytes=abc", 1000), ByteRange::Full);
+    }
+}
@@ -0,0 +1,6 @@
+pub mod byte_range;
+pub mod recording_dto;
+pub mod recording_entity;
+pub mod recording_record;
+pub mod recording_routes;
+pub mod recording_service;
@@ -0,0 +1,147 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::features::streams::{
+    pipeline::{EncodingProfile, SegmentPolicy},
+    recording_manager::RecordingState,
+    stream_error::StreamError,
+};
+
+use super::recording_entity::Recording;
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct RecordingDto {
+    pub id: Uuid,
+    /// Set on the segments of a continuous recording.
+    pub parent_id: Option<Uuid>,
+    pub owner_id: Option<Uuid>,
+    pub source: String,
+    pub state: RecordingState,
+    pub file_path: String,
+    pub codec: String,
+    pub container: String,
+    /// Recorded duration in milliseconds, known once the recording finished.
+    pub duration: Option<u64>,
+    pub size: Option<u64>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub error: Option<StreamError>,
+    /// Served at `/api/recordings/:id/poster` once generated.
+    pub poster_path: Option<String>,
+    /// Served at `/api/recordings/:id/thumbnails/:index`, from 0.
+    pub thumbnail_paths: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct RecordingCreateDto {
+    /// Name of the configured source, the first source when omitted.
+    pub source: Option<String>,
+    /// Recording duration in seconds, overrides `media.recording_duration`.
+    /// Zero records until stopped.
+    pub duration: Option<u64>,
+    /// Rotates the recording in segments of this many seconds.
+    pub segment_duration: Option<u64>,
+    /// Rotates the recording in segments of this many megabytes.
+    pub segment_size: Option<u64>,
+    /// Name of an encoding profile from `media.profiles`.
+    pub profile: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct EncodingProfileDto {
+    pub name: String,
+    /// GStreamer element encoding the video.
+    pub encoder: String,
+    pub codec: String,
+    /// Whether the encoder is installed on this host.
+    pub available: bool,
+    pub bitrate: Option<u32>,
+    pub gop: Option<u32>,
+    pub preset: Option<String>,
+    pub tune: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub framerate: Option<u32>,
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct RecordingContentQuery {
+    /// Asks browsers to save the file instead of playing it.
+    #[serde(default)]
+    pub download: bool,
+}
+
+pub fn get_recording_dto(recording: Recording) -> RecordingDto {
+    RecordingDto {
+        id: recording.id,
+        parent_id: recording.parent_id,
+        owner_id: recording.owner_id,
+        source: recording.source,
+        state: recording.status,
+        file_path: recording.file_path,
+        codec: recording.codec,
+        container: recording.container,
+        duration: recording
+            .duration
+            .map(|duration| duration.as_millis() as u64),
+        size: recording.size,
+        started_at: recording.started_at,
+        finished_at: recording.finished_at,
+        error: recording.error,
+        poster_path: recording.poster_path,
+        thumbnail_paths: recording.thumbnail_paths,
+    }
+}
+
+pub fn get_encoding_profile_dto(
+    name: String,
+    profile: EncodingProfile,
+    available: bool,
+) -> EncodingProfileDto {
+    EncodingProfileDto {
+        name,
+        encoder: profile.encoder.element_name().to_owned(),
+        codec: profile.encoder.codec().to_owned(),
+        available,
+        bitrate: profile.bitrate,
+        gop: profile.gop,
+        preset: profile.preset,
+        tune: profile.tune,
+        width: profile.width,
+        height: profile.height,
+        framerate: profile.framerate,
+    }
+}
+
+/// Fails with the name of the first setting whose value does not fit the
+/// muxer, which takes nanoseconds and bytes.
+pub fn get_segment_policy(
+    recording_dto: &RecordingCreateDto,
+) -> Result<Option<SegmentPolicy>, &'static str> {
+    if recording_dto.segment_duration.is_none() && recording_dto.segment_size.is_none() {
+        return Ok(None);
+    }
+
+    let max_duration = recording_dto
+        .segment_duration
+        .map(|duration| {
+            duration
+                .checked_mul(1_000_000_000)
+                .map(|_| Duration::from_secs(duration))
+                .ok_or("segment_duration")
+        })
+        .transpose()?;
+    let max_size_bytes = recording_dto
+        .segment_size
+        .map(|size| size.checked_mul(1024 * 1024).ok_or("segment_size"))
+        .transpose()?;
+
+    Ok(Some(SegmentPolicy {
+        max_duration,
+        max_size_bytes,
+    }))
+}
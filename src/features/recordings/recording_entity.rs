@@ -0,0 +1,26 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::features::streams::{recording_manager::RecordingState, stream_error::StreamError};
+
+#[derive(Clone, Serialize, Debug)]
+pub struct Recording {
+    pub id: Uuid,
+    pub parent_id: Option<Uuid>,
+    pub owner_id: Option<Uuid>,
+    pub source: String,
+    pub file_path: String,
+    pub codec: String,
+    pub container: String,
+    pub duration: Option<Duration>,
+    pub size: Option<u64>,
+    pub status: RecordingState,
+    pub error: Option<StreamError>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub poster_path: Option<String>,
+    pub thumbnail_paths: Vec<String>,
+}
@@ -0,0 +1,313 @@
+use std::io::SeekFrom;
+use std::time::Duration;
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+use crate::{
+    application::ApplicationState,
+    features::{
+        auth::{auth_entity::AuthenticatedUser, auth_middleware::require_role},
+        streams::{
+            pipeline::PipelineError,
+            recording_manager::{RecordingManagerError, RecordingRequest},
+        },
+        users::user_entity::Role,
+    },
+    service::ServiceType,
+};
+
+use super::{
+    byte_range::ByteRange,
+    recording_dto::{
+        get_encoding_profile_dto, get_recording_dto, get_segment_policy, EncodingProfileDto,
+        RecordingContentQuery, RecordingCreateDto, RecordingDto,
+    },
+    recording_service::{RecordingContent, RecordingService, RecordingServiceError},
+};
+
+impl IntoResponse for RecordingManagerError {
+    fn into_response(self) -> Response {
+        if let RecordingManagerError::InvalidPipeline(PipelineError::MissingElements(missing)) =
+            &self
+        {
+            let body = axum::Json(serde_json::json!({
+                "error": self.to_string(),
+                "missing_elements": missing,
+            }));
+            return (StatusCode::INTERNAL_SERVER_ERROR, body).into_response();
+        }
+
+        let status_code = match self {
+            RecordingManagerError::MediaDisabled | RecordingManagerError::PreviewDisabled => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            RecordingManagerError::RecordingNotFound(_) => StatusCode::NOT_FOUND,
+            RecordingManagerError::SourceNotFound(_)
+            | RecordingManagerError::ProfileNotFound(_)
+            | RecordingManagerError::InvalidDuration(_) => StatusCode::BAD_REQUEST,
+            RecordingManagerError::RecordingNotActive(_)
+            | RecordingManagerError::SourceMounted(_) => StatusCode::CONFLICT,
+            RecordingManagerError::InvalidPipeline(_)
+            | RecordingManagerError::StreamError(_)
+            | RecordingManagerError::IoError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        let body = axum::Json(serde_json::json!({ "error": self.to_string() }));
+        (status_code, body).into_response()
+    }
+}
+
+impl IntoResponse for RecordingServiceError {
+    fn into_response(self) -> Response {
+        let status_code = match self {
+            RecordingServiceError::RecordingNotFound(_)
+            | RecordingServiceError::PreviewNotFound(_)
+            | RecordingServiceError::ContentNotFound(_) => StatusCode::NOT_FOUND,
+            RecordingServiceError::RecordingNotFinished(_) => StatusCode::CONFLICT,
+            RecordingServiceError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            RecordingServiceError::RecordingManagerError(err) => return err.into_response(),
+            RecordingServiceError::DatabaseError(_) | RecordingServiceError::IoError(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+
+        let body = axum::Json(serde_json::json!({ "error": self.to_string() }));
+        (status_code, body).into_response()
+    }
+}
+
+pub fn routes(state: ApplicationState) -> Router {
+    let recording_service = match state.service_provider.get_service("RecordingService") {
+        Some(ServiceType::RecordingService(recording_service)) => recording_service,
+        _ => panic!("RecordingService not found in ServiceProvider"),
+    };
+
+    // Viewers can only watch
+    let operator_routes = Router::new()
+        .route("/", post(handle_start_recording))
+        .route("/:id/stop", post(handle_stop_recording))
+        .route_layer(middleware::from_fn_with_state(Role::Operator, require_role));
+
+    Router::new()
+        .route("/", get(handle_list_recordings))
+        .route("/profiles", get(handle_list_profiles))
+        .route("/:id", get(handle_read_recording))
+        .route("/:id/segments", get(handle_list_segments))
+        .route("/:id/content", get(handle_read_recording_content))
+        .route("/:id/poster", get(handle_read_poster))
+        .route("/:id/thumbnails/:index", get(handle_read_thumbnail))
+        .route_layer(middleware::from_fn_with_state(Role::Viewer, require_role))
+        .merge(operator_routes)
+        .with_state(recording_service)
+}
+
+async fn handle_start_recording(
+    State(service): State<RecordingService>,
+    user: AuthenticatedUser,
+    Json(recording_dto): Json<RecordingCreateDto>,
+) -> Result<impl IntoResponse, RecordingServiceError> {
+    let segments = get_segment_policy(&recording_dto)
+        .map_err(|setting| RecordingServiceError::InvalidRequest(setting.to_owned()))?;
+    let recording = service
+        .start_recording(RecordingRequest {
+            owner_id: Some(user.id),
+            segments,
+            source: recording_dto.source,
+            duration: recording_dto.duration.map(Duration::from_secs),
+            profile: recording_dto.profile,
+        })
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(get_recording_dto(recording))))
+}
+
+async fn handle_stop_recording(
+    State(service): State<RecordingService>,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, RecordingServiceError> {
+    let recording = service.stop_recording(id).await?;
+
+    Ok((StatusCode::ACCEPTED, Json(get_recording_dto(recording))))
+}
+
+async fn handle_read_recording(
+    State(service): State<RecordingService>,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, RecordingServiceError> {
+    let recording = service.read_recording(id).await?;
+
+    Ok((StatusCode::OK, Json(get_recording_dto(recording))))
+}
+
+async fn handle_list_recordings(
+    State(service): State<RecordingService>,
+) -> Result<impl IntoResponse, RecordingServiceError> {
+    let recording_dtos: Vec<RecordingDto> = service
+        .list_recordings()
+        .await?
+        .into_iter()
+        .map(get_recording_dto)
+        .collect();
+
+    Ok(Json(recording_dtos))
+}
+
+async fn handle_list_profiles(State(service): State<RecordingService>) -> impl IntoResponse {
+    let profile_dtos: Vec<EncodingProfileDto> = service
+        .list_profiles()
+        .into_iter()
+        .map(|(name, profile, available)| get_encoding_profile_dto(name, profile, available))
+        .collect();
+
+    Json(profile_dtos)
+}
+
+async fn handle_list_segments(
+    State(service): State<RecordingService>,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, RecordingServiceError> {
+    let segment_dtos: Vec<RecordingDto> = service
+        .list_segments(id)
+        .await?
+        .into_iter()
+        .map(get_recording_dto)
+        .collect();
+
+    Ok(Json(segment_dtos))
+}
+
+async fn handle_read_poster(
+    State(service): State<RecordingService>,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, RecordingServiceError> {
+    let poster = service.read_poster(id).await?;
+
+    Ok(([(header::CONTENT_TYPE, "image/jpeg")], poster))
+}
+
+async fn handle_read_thumbnail(
+    State(service): State<RecordingService>,
+    Path((id, index)): Path<(Uuid, usize)>,
+) -> Result<impl IntoResponse, RecordingServiceError> {
+    let thumbnail = service.read_thumbnail(id, index).await?;
+
+    Ok(([(header::CONTENT_TYPE, "image/jpeg")], thumbnail))
+}
+
+async fn handle_read_recording_content(
+    State(service): State<RecordingService>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<RecordingContentQuery>,
+    headers: HeaderMap,
+) -> Result<Response, RecordingServiceError> {
+    let content = service.open_recording_content(id).await?;
+    let mut response_headers = get_content_headers(&content, query.download);
+
+    let if_none_match = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok());
+    if if_none_match.is_some_and(|tags| etag_matches(tags, &content.etag)) {
+        return Ok((StatusCode::NOT_MODIFIED, response_headers).into_response());
+    }
+
+    // A range only applies to the version of the file the client already has
+    let if_range = headers
+        .get(header::IF_RANGE)
+        .and_then(|value| value.to_str().ok());
+    let range = match headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(range) if if_range.map_or(true, |tag| tag == content.etag) => {
+            ByteRange::parse(range, content.size)
+        }
+        _ => ByteRange::Full,
+    };
+
+    let RecordingContent { mut file, size, .. } = content;
+    let (status_code, start, length) = match range {
+        ByteRange::Full => (StatusCode::OK, 0, size),
+        ByteRange::Partial { start, end } => {
+            response_headers.insert(
+                header::CONTENT_RANGE,
+                header_value(&format!("bytes {}-{}/{}", start, end, size)),
+            );
+            (StatusCode::PARTIAL_CONTENT, start, end - start + 1)
+        }
+        ByteRange::Unsatisfiable => {
+            response_headers.insert(
+                header::CONTENT_RANGE,
+                header_value(&format!("bytes */{}", size)),
+            );
+            return Ok((StatusCode::RANGE_NOT_SATISFIABLE, response_headers).into_response());
+        }
+    };
+
+    file.seek(SeekFrom::Start(start))
+        .await
+        .map_err(|err| RecordingServiceError::IoError(err.to_string()))?;
+    response_headers.insert(header::CONTENT_LENGTH, HeaderValue::from(length));
+
+    Ok((
+        status_code,
+        response_headers,
+        Body::from_stream(ReaderStream::new(file.take(length))),
+    )
+        .into_response())
+}
+
+fn get_content_headers(content: &RecordingContent, download: bool) -> HeaderMap {
+    // Quotes and non-ASCII characters cannot appear in the quoted file name
+    let file_name: String = content
+        .file_name
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' => '_',
+            c if c.is_ascii() && !c.is_ascii_control() => c,
+            _ => '_',
+        })
+        .collect();
+    let disposition = match download {
+        true => "attachment",
+        false => "inline",
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, header_value(content.content_type));
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    headers.insert(header::ETAG, header_value(&content.etag));
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        header_value(&format!("{}; filename=\"{}\"", disposition, file_name)),
+    );
+    if let Some(modified_at) = content.modified_at {
+        headers.insert(
+            header::LAST_MODIFIED,
+            header_value(&modified_at.format("%a, %d %b %Y %H:%M:%S GMT").to_string()),
+        );
+    }
+
+    headers
+}
+
+/// Whether an `If-None-Match` list names `etag`, compared weakly.
+fn etag_matches(tags: &str, etag: &str) -> bool {
+    tags.split(',')
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+fn header_value(value: &str) -> HeaderValue {
+    HeaderValue::from_str(value).unwrap_or_else(|_| HeaderValue::from_static(""))
+}
@@ -0,0 +1,474 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    sea_query::OnConflict, ActiveValue::NotSet, ColumnTrait, DatabaseConnection, DbErr,
+    EntityTrait, QueryFilter, QueryOrder, Set,
+};
+use thiserror::Error;
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
+
+use crate::features::streams::{
+    pipeline::{Container, EncodingProfile},
+    recording_manager::{
+        RecordingInfo, RecordingManager, RecordingManagerError, RecordingRequest, RecordingState,
+    },
+    stream_error::StreamError,
+};
+use crate::service::Service;
+
+use super::{
+    recording_entity::Recording,
+    recording_record::{self, ActiveModel, Entity as RecordingRecord},
+};
+
+#[derive(Debug, Error)]
+pub enum RecordingServiceError {
+    #[error("Recording with id {0} not found")]
+    RecordingNotFound(Uuid),
+    #[error("Recording with id {0} has no such preview")]
+    PreviewNotFound(Uuid),
+    #[error("Recording with id {0} has no content")]
+    ContentNotFound(Uuid),
+    #[error("Recording with id {0} is not finished")]
+    RecordingNotFinished(Uuid),
+    #[error("Value of {0} is out of range")]
+    InvalidRequest(String),
+    #[error(transparent)]
+    RecordingManagerError(#[from] RecordingManagerError),
+    #[error("Database error: {0}")]
+    DatabaseError(String),
+    #[error("I/O error: {0}")]
+    IoError(String),
+}
+
+/// File of a finished recording, opened for download.
+pub struct RecordingContent {
+    pub file: tokio::fs::File,
+    pub file_name: String,
+    pub content_type: &'static str,
+    pub size: u64,
+    /// Changes whenever the file is rewritten, derived from its size and
+    /// modification time.
+    pub etag: String,
+    pub modified_at: Option<DateTime<Utc>>,
+}
+
+/// Front for the recordings feature: drives captures through the
+/// `RecordingManager` and keeps their metadata in the database.
+#[derive(Clone)]
+pub struct RecordingService {
+    pub name: String,
+    connection: Option<Arc<DatabaseConnection>>,
+    manager: RecordingManager,
+}
+
+impl Service for RecordingService {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl RecordingService {
+    pub fn new(connection: Option<Arc<DatabaseConnection>>, manager: RecordingManager) -> Self {
+        Self {
+            name: String::from("RecordingService"),
+            connection,
+            manager,
+        }
+    }
+
+    /// Spawns a task persisting every recording update published by the manager.
+    pub fn watch_recording_events(&self) {
+        let Some(connection) = self.connection.clone() else {
+            return;
+        };
+        let mut events = self.manager.subscribe();
+
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(info) => {
+                        let recording = get_recording_from_info(info);
+                        if let Err(err) = save_recording(&connection, recording).await {
+                            tracing::error!("Failed to persist recording: {}", err);
+                        }
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::warn!("Recording persistence skipped {} updates", skipped);
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    pub async fn start_recording(
+        &self,
+        request: RecordingRequest,
+    ) -> Result<Recording, RecordingServiceError> {
+        let info = self.manager.start(request)?;
+
+        Ok(get_recording_from_info(info))
+    }
+
+    pub async fn stop_recording(&self, id: Uuid) -> Result<Recording, RecordingServiceError> {
+        match self.manager.stop(id) {
+            Ok(info) => Ok(get_recording_from_info(info)),
+            Err(RecordingManagerError::RecordingNotFound(_)) => {
+                // Recordings from a previous run are only known to the database
+                self.read_recording(id).await?;
+                Err(RecordingManagerError::RecordingNotActive(id).into())
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub async fn read_recording(&self, id: Uuid) -> Result<Recording, RecordingServiceError> {
+        if let Ok(info) = self.manager.get(id) {
+            return Ok(get_recording_from_info(info));
+        }
+
+        let connection = self
+            .connection
+            .as_ref()
+            .ok_or(RecordingServiceError::RecordingNotFound(id))?;
+
+        let recording_record = RecordingRecord::find_by_id(id)
+            .one(connection.as_ref())
+            .await
+            .map_err(|err| RecordingServiceError::DatabaseError(err.to_string()))?
+            .ok_or(RecordingServiceError::RecordingNotFound(id))?;
+
+        Ok(get_recording_from_record(recording_record))
+    }
+
+    /// Opens the file of a finished recording. Segmented recordings have no
+    /// file of their own, their segments are downloaded instead.
+    pub async fn open_recording_content(
+        &self,
+        id: Uuid,
+    ) -> Result<RecordingContent, RecordingServiceError> {
+        let recording = self.read_recording(id).await?;
+        if matches!(
+            recording.status,
+            RecordingState::Pending | RecordingState::Recording | RecordingState::Finalizing
+        ) {
+            return Err(RecordingServiceError::RecordingNotFinished(id));
+        }
+
+        let open_error = |err: std::io::Error| match err.kind() {
+            std::io::ErrorKind::NotFound => RecordingServiceError::ContentNotFound(id),
+            _ => RecordingServiceError::IoError(err.to_string()),
+        };
+        let file = tokio::fs::File::open(&recording.file_path)
+            .await
+            .map_err(open_error)?;
+        let metadata = file.metadata().await.map_err(open_error)?;
+        if !metadata.is_file() {
+            return Err(RecordingServiceError::ContentNotFound(id));
+        }
+
+        let modified_at = metadata.modified().ok().map(DateTime::<Utc>::from);
+        let etag = format!(
+            "\"{:x}-{:x}\"",
+            metadata.len(),
+            modified_at.map_or(0, |modified_at| modified_at.timestamp_millis())
+        );
+        let file_name = std::path::Path::new(&recording.file_path)
+            .file_name()
+            .map(|file_name| file_name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| id.to_string());
+        let content_type = recording
+            .container
+            .parse::<Container>()
+            .map_or("application/octet-stream", |container| {
+                container.content_type()
+            });
+
+        Ok(RecordingContent {
+            file,
+            file_name,
+            content_type,
+            size: metadata.len(),
+            etag,
+            modified_at,
+        })
+    }
+
+    /// Reads the poster frame of a recording, a JPEG image.
+    pub async fn read_poster(&self, id: Uuid) -> Result<Vec<u8>, RecordingServiceError> {
+        let recording = self.read_recording(id).await?;
+
+        read_preview(id, recording.poster_path.as_deref()).await
+    }
+
+    /// Reads the `index`-th image of the thumbnail strip of a recording.
+    pub async fn read_thumbnail(
+        &self,
+        id: Uuid,
+        index: usize,
+    ) -> Result<Vec<u8>, RecordingServiceError> {
+        let recording = self.read_recording(id).await?;
+
+        read_preview(id, recording.thumbnail_paths.get(index).map(String::as_str)).await
+    }
+
+    pub fn list_profiles(&self) -> Vec<(String, EncodingProfile, bool)> {
+        self.manager.list_profiles()
+    }
+
+    /// Lists top-level recordings, segments are listed with `list_segments`.
+    pub async fn list_recordings(&self) -> Result<Vec<Recording>, RecordingServiceError> {
+        self.find_recordings(None).await
+    }
+
+    pub async fn list_segments(
+        &self,
+        parent_id: Uuid,
+    ) -> Result<Vec<Recording>, RecordingServiceError> {
+        self.read_recording(parent_id).await?;
+
+        self.find_recordings(Some(parent_id)).await
+    }
+
+    /// Lists persisted recordings with the given parent, with live state taking
+    /// precedence for the ones still handled by the manager. Most recent
+    /// recordings come first.
+    async fn find_recordings(
+        &self,
+        parent_id: Option<Uuid>,
+    ) -> Result<Vec<Recording>, RecordingServiceError> {
+        let mut recordings: HashMap<Uuid, Recording> = HashMap::new();
+
+        if let Some(connection) = &self.connection {
+            let parent_condition = match parent_id {
+                Some(parent_id) => recording_record::Column::ParentId.eq(parent_id),
+                None => recording_record::Column::ParentId.is_null(),
+            };
+            let recording_records = RecordingRecord::find()
+                .filter(parent_condition)
+                .order_by_desc(recording_record::Column::StartedAt)
+                .all(connection.as_ref())
+                .await
+                .map_err(|err| RecordingServiceError::DatabaseError(err.to_string()))?;
+
+            recordings.extend(
+                recording_records
+                    .into_iter()
+                    .map(|record| (record.id, get_recording_from_record(record))),
+            );
+        }
+
+        recordings.extend(
+            self.manager
+                .list()
+                .into_iter()
+                .filter(|info| info.parent_id == parent_id)
+                .map(|info| (info.id, get_recording_from_info(info))),
+        );
+
+        let mut recordings: Vec<Recording> = recordings.into_values().collect();
+        recordings.sort_by_key(|recording| Reverse(recording.started_at));
+
+        Ok(recordings)
+    }
+}
+
+/// Settles the recordings a previous run left active, it ended without
+/// finalizing them. Files in a crash-safe container, and segmented recordings
+/// with finished segments, are kept as completed when data was written. The
+/// others cannot be played and are marked as failed.
+pub async fn recover_interrupted_recordings(
+    connection: &DatabaseConnection,
+) -> Result<usize, DbErr> {
+    let active_states = [
+        RecordingState::Pending,
+        RecordingState::Recording,
+        RecordingState::Finalizing,
+    ]
+    .map(|state| state.as_str());
+    let mut recording_records = RecordingRecord::find()
+        .filter(recording_record::Column::Status.is_in(active_states))
+        .all(connection)
+        .await?;
+    // Segments are settled before the recordings they belong to
+    recording_records.sort_by_key(|record| record.parent_id.is_none());
+
+    let recovered = recording_records.len();
+    for record in recording_records {
+        let segments = RecordingRecord::find()
+            .filter(recording_record::Column::ParentId.eq(record.id))
+            .all(connection)
+            .await?;
+        let mut recording = get_recording_from_record(record);
+
+        let (size, finished_at, playable) = if segments.is_empty() {
+            let metadata = tokio::fs::metadata(&recording.file_path).await.ok();
+            let size = metadata.as_ref().map_or(0, |metadata| metadata.len());
+            let modified_at = metadata
+                .and_then(|metadata| metadata.modified().ok())
+                .map(DateTime::<Utc>::from);
+            let crash_safe = recording
+                .container
+                .parse::<Container>()
+                .is_ok_and(|container| container.is_crash_safe());
+
+            (size, modified_at, crash_safe && size > 0)
+        } else {
+            let completed: Vec<_> = segments
+                .iter()
+                .filter(|segment| segment.status == RecordingState::Completed.as_str())
+                .collect();
+            let size = completed
+                .iter()
+                .filter_map(|segment| segment.size_bytes)
+                .sum::<i64>() as u64;
+            let finished_at = completed
+                .iter()
+                .filter_map(|segment| segment.finished_at)
+                .max();
+
+            (size, finished_at, !completed.is_empty())
+        };
+
+        recording.size = Some(size);
+        recording.finished_at = Some(finished_at.unwrap_or_else(Utc::now));
+        if playable {
+            recording.status = RecordingState::Completed;
+        } else {
+            recording.status = RecordingState::Failed;
+            recording.error = Some(StreamError::Interrupted);
+        }
+        tracing::info!(
+            "Recording {} was interrupted, marked as {}",
+            recording.id,
+            recording.status.as_str()
+        );
+
+        save_recording(connection, recording).await?;
+    }
+
+    Ok(recovered)
+}
+
+async fn read_preview(id: Uuid, path: Option<&str>) -> Result<Vec<u8>, RecordingServiceError> {
+    let path = path.ok_or(RecordingServiceError::PreviewNotFound(id))?;
+
+    tokio::fs::read(path).await.map_err(|err| match err.kind() {
+        std::io::ErrorKind::NotFound => RecordingServiceError::PreviewNotFound(id),
+        _ => RecordingServiceError::IoError(err.to_string()),
+    })
+}
+
+/// Inserts the recording or updates the columns that change during its lifetime.
+async fn save_recording(
+    connection: &DatabaseConnection,
+    recording: Recording,
+) -> Result<(), DbErr> {
+    let model = ActiveModel {
+        id: Set(recording.id),
+        parent_id: Set(recording.parent_id),
+        owner_id: Set(recording.owner_id),
+        source: Set(recording.source),
+        file_path: Set(recording.file_path),
+        codec: Set(recording.codec),
+        container: Set(recording.container),
+        duration_ms: Set(recording
+            .duration
+            .map(|duration| duration.as_millis() as i64)),
+        size_bytes: Set(recording.size.map(|size| size as i64)),
+        status: Set(recording.status.as_str().to_owned()),
+        error: Set(recording
+            .error
+            .map(|error| serde_json::to_string(&error).unwrap_or(error.to_string()))),
+        started_at: Set(recording.started_at),
+        finished_at: Set(recording.finished_at),
+        poster_path: Set(recording.poster_path),
+        thumbnail_paths: Set((!recording.thumbnail_paths.is_empty())
+            .then(|| serde_json::to_string(&recording.thumbnail_paths).unwrap_or_default())),
+        created_at: NotSet,
+        updated_at: Set(Utc::now()),
+    };
+
+    RecordingRecord::insert(model)
+        .on_conflict(
+            OnConflict::column(recording_record::Column::Id)
+                .update_columns([
+                    recording_record::Column::FilePath,
+                    recording_record::Column::DurationMs,
+                    recording_record::Column::SizeBytes,
+                    recording_record::Column::Status,
+                    recording_record::Column::Error,
+                    recording_record::Column::FinishedAt,
+                    recording_record::Column::PosterPath,
+                    recording_record::Column::ThumbnailPaths,
+                    recording_record::Column::UpdatedAt,
+                ])
+                .to_owned(),
+        )
+        .exec(connection)
+        .await?;
+
+    Ok(())
+}
+
+fn get_recording_from_info(info: RecordingInfo) -> Recording {
+    Recording {
+        id: info.id,
+        parent_id: info.parent_id,
+        owner_id: info.owner_id,
+        source: info.source,
+        file_path: info.location.display().to_string(),
+        codec: info.codec,
+        container: info.container,
+        duration: info.duration,
+        size: info.size,
+        status: info.state,
+        error: info.error,
+        started_at: info.started_at,
+        finished_at: info.finished_at,
+        poster_path: info
+            .previews
+            .poster
+            .map(|poster| poster.display().to_string()),
+        thumbnail_paths: info
+            .previews
+            .thumbnails
+            .iter()
+            .map(|thumbnail| thumbnail.display().to_string())
+            .collect(),
+    }
+}
+
+fn get_recording_from_record(record: recording_record::Model) -> Recording {
+    Recording {
+        id: record.id,
+        parent_id: record.parent_id,
+        owner_id: record.owner_id,
+        source: record.source,
+        file_path: record.file_path,
+        codec: record.codec,
+        container: record.container,
+        duration: record
+            .duration_ms
+            .map(|duration| Duration::from_millis(duration as u64)),
+        size: record.size_bytes.map(|size| size as u64),
+        status: record.status.parse().unwrap_or(RecordingState::Failed),
+        // Errors are stored as JSON, plain text is kept as an opaque message
+        error: record.error.map(|error| {
+            serde_json::from_str(&error).unwrap_or(StreamError::Other { message: error })
+        }),
+        started_at: record.started_at,
+        finished_at: record.finished_at,
+        poster_path: record.poster_path,
+        thumbnail_paths: record
+            .thumbnail_paths
+            .and_then(|paths| serde_json::from_str(&paths).ok())
+            .unwrap_or_default(),
+    }
+}
@@ -0,0 +1,5 @@
+pub mod snapshot_dto;
+pub mod snapshot_entity;
+pub mod snapshot_record;
+pub mod snapshot_routes;
+pub mod snapshot_service;
@@ -0,0 +1,31 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::features::streams::snapshot::SnapshotFormat;
+
+use super::snapshot_entity::Snapshot;
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SnapshotDto {
+    pub id: Uuid,
+    pub owner_id: Option<Uuid>,
+    pub source: String,
+    pub file_path: String,
+    pub format: SnapshotFormat,
+    pub size: u64,
+    pub created_at: DateTime<Utc>,
+}
+
+pub fn get_snapshot_dto(snapshot: Snapshot) -> SnapshotDto {
+    SnapshotDto {
+        id: snapshot.id,
+        owner_id: snapshot.owner_id,
+        source: snapshot.source,
+        file_path: snapshot.file_path,
+        format: snapshot.format,
+        size: snapshot.size,
+        created_at: snapshot.created_at,
+    }
+}
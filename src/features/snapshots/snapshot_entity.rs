@@ -0,0 +1,16 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::features::streams::snapshot::SnapshotFormat;
+
+#[derive(Clone, Serialize, Debug)]
+pub struct Snapshot {
+    pub id: Uuid,
+    pub owner_id: Option<Uuid>,
+    pub source: String,
+    pub file_path: String,
+    pub format: SnapshotFormat,
+    pub size: u64,
+    pub created_at: DateTime<Utc>,
+}
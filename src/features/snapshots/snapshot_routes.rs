@@ -0,0 +1,83 @@
+use axum::{
+    extract::{Path, State},
+    http::{header, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use uuid::Uuid;
+
+use crate::{
+    application::ApplicationState,
+    features::{auth::auth_middleware::require_role, users::user_entity::Role},
+    service::ServiceType,
+};
+
+use super::{
+    snapshot_dto::{get_snapshot_dto, SnapshotDto},
+    snapshot_service::{SnapshotService, SnapshotServiceError},
+};
+
+impl IntoResponse for SnapshotServiceError {
+    fn into_response(self) -> Response {
+        let status_code = match self {
+            SnapshotServiceError::SnapshotNotFound(_) => StatusCode::NOT_FOUND,
+            SnapshotServiceError::PersistenceDisabled => StatusCode::SERVICE_UNAVAILABLE,
+            SnapshotServiceError::DatabaseError(_) | SnapshotServiceError::IoError(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+
+        let body = axum::Json(serde_json::json!({ "error": self.to_string() }));
+        (status_code, body).into_response()
+    }
+}
+
+pub fn routes(state: ApplicationState) -> Router {
+    let snapshot_service = match state.service_provider.get_service("SnapshotService") {
+        Some(ServiceType::SnapshotService(snapshot_service)) => snapshot_service,
+        _ => panic!("SnapshotService not found in ServiceProvider"),
+    };
+
+    Router::new()
+        .route("/", get(handle_list_snapshots))
+        .route("/:id", get(handle_read_snapshot))
+        .route("/:id/content", get(handle_read_snapshot_content))
+        .route_layer(middleware::from_fn_with_state(Role::Viewer, require_role))
+        .with_state(snapshot_service)
+}
+
+async fn handle_read_snapshot(
+    State(service): State<SnapshotService>,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, SnapshotServiceError> {
+    let snapshot = service.read_snapshot(id).await?;
+
+    Ok((StatusCode::OK, Json(get_snapshot_dto(snapshot))))
+}
+
+async fn handle_read_snapshot_content(
+    State(service): State<SnapshotService>,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, SnapshotServiceError> {
+    let (snapshot, content) = service.read_snapshot_content(id).await?;
+
+    Ok((
+        [(header::CONTENT_TYPE, snapshot.format.content_type())],
+        content,
+    ))
+}
+
+async fn handle_list_snapshots(
+    State(service): State<SnapshotService>,
+) -> Result<impl IntoResponse, SnapshotServiceError> {
+    let snapshot_dtos: Vec<SnapshotDto> = service
+        .list_snapshots()
+        .await?
+        .into_iter()
+        .map(get_snapshot_dto)
+        .collect();
+
+    Ok(Json(snapshot_dtos))
+}
@@ -0,0 +1,169 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use axum::body::Bytes;
+use chrono::Utc;
+use sea_orm::{DatabaseConnection, EntityTrait, QueryOrder, Set};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::configuration::MediaConfiguration;
+use crate::features::streams::{
+    output_path::{allocate_output_path, TemplateContext},
+    snapshot::SnapshotFormat,
+};
+use crate::service::Service;
+
+use super::{
+    snapshot_entity::Snapshot,
+    snapshot_record::{self, ActiveModel, Entity as SnapshotRecord},
+};
+
+#[derive(Debug, Error)]
+pub enum SnapshotServiceError {
+    #[error("Snapshot with id {0} not found")]
+    SnapshotNotFound(Uuid),
+    #[error("Snapshots cannot be kept without a database")]
+    PersistenceDisabled,
+    #[error("Database error: {0}")]
+    DatabaseError(String),
+    #[error("I/O error: {0}")]
+    IoError(String),
+}
+
+/// Still frames kept as media assets, written to the output folder with
+/// their metadata in the database.
+#[derive(Clone)]
+pub struct SnapshotService {
+    pub name: String,
+    connection: Option<Arc<DatabaseConnection>>,
+    configuration: Arc<MediaConfiguration>,
+}
+
+impl Service for SnapshotService {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl SnapshotService {
+    pub fn new(
+        connection: Option<Arc<DatabaseConnection>>,
+        configuration: MediaConfiguration,
+    ) -> Self {
+        Self {
+            name: String::from("SnapshotService"),
+            connection,
+            configuration: Arc::new(configuration),
+        }
+    }
+
+    /// Writes `content` next to the recordings of `source` and records it.
+    pub async fn save_snapshot(
+        &self,
+        source: &str,
+        format: SnapshotFormat,
+        content: &Bytes,
+        owner_id: Option<Uuid>,
+    ) -> Result<Snapshot, SnapshotServiceError> {
+        let connection = self
+            .connection
+            .as_ref()
+            .ok_or(SnapshotServiceError::PersistenceDisabled)?;
+
+        let id = Uuid::new_v4();
+        let created_at = Utc::now();
+        let location = allocate_output_path(
+            Path::new(&self.configuration.output_folder),
+            &self.configuration.file_name_template,
+            &TemplateContext {
+                id,
+                source,
+                extension: format.extension(),
+                timestamp: created_at,
+            },
+        )
+        .map_err(|err| SnapshotServiceError::IoError(err.to_string()))?;
+        tokio::fs::write(&location, content)
+            .await
+            .map_err(|err| SnapshotServiceError::IoError(err.to_string()))?;
+
+        let model = ActiveModel {
+            id: Set(id),
+            owner_id: Set(owner_id),
+            source: Set(source.to_owned()),
+            file_path: Set(location.display().to_string()),
+            format: Set(format.name().to_owned()),
+            size_bytes: Set(content.len() as i64),
+            created_at: Set(created_at),
+        };
+        let snapshot_record = SnapshotRecord::insert(model)
+            .exec_with_returning(connection.as_ref())
+            .await
+            .map_err(|err| SnapshotServiceError::DatabaseError(err.to_string()))?;
+
+        Ok(get_snapshot_from_record(snapshot_record))
+    }
+
+    pub async fn read_snapshot(&self, id: Uuid) -> Result<Snapshot, SnapshotServiceError> {
+        let connection = self
+            .connection
+            .as_ref()
+            .ok_or(SnapshotServiceError::SnapshotNotFound(id))?;
+
+        let snapshot_record = SnapshotRecord::find_by_id(id)
+            .one(connection.as_ref())
+            .await
+            .map_err(|err| SnapshotServiceError::DatabaseError(err.to_string()))?
+            .ok_or(SnapshotServiceError::SnapshotNotFound(id))?;
+
+        Ok(get_snapshot_from_record(snapshot_record))
+    }
+
+    /// Reads the image of a snapshot.
+    pub async fn read_snapshot_content(
+        &self,
+        id: Uuid,
+    ) -> Result<(Snapshot, Vec<u8>), SnapshotServiceError> {
+        let snapshot = self.read_snapshot(id).await?;
+        let content =
+            tokio::fs::read(&snapshot.file_path)
+                .await
+                .map_err(|err| match err.kind() {
+                    std::io::ErrorKind::NotFound => SnapshotServiceError::SnapshotNotFound(id),
+                    _ => SnapshotServiceError::IoError(err.to_string()),
+                })?;
+
+        Ok((snapshot, content))
+    }
+
+    /// Lists persisted snapshots, most recent first.
+    pub async fn list_snapshots(&self) -> Result<Vec<Snapshot>, SnapshotServiceError> {
+        let Some(connection) = &self.connection else {
+            return Ok(Vec::new());
+        };
+
+        let snapshot_records = SnapshotRecord::find()
+            .order_by_desc(snapshot_record::Column::CreatedAt)
+            .all(connection.as_ref())
+            .await
+            .map_err(|err| SnapshotServiceError::DatabaseError(err.to_string()))?;
+
+        Ok(snapshot_records
+            .into_iter()
+            .map(get_snapshot_from_record)
+            .collect())
+    }
+}
+
+fn get_snapshot_from_record(record: snapshot_record::Model) -> Snapshot {
+    Snapshot {
+        id: record.id,
+        owner_id: record.owner_id,
+        source: record.source,
+        file_path: record.file_path,
+        format: record.format.parse().unwrap_or_default(),
+        size: record.size_bytes as u64,
+        created_at: record.created_at,
+    }
+}
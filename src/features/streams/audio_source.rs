@@ -0,0 +1,55 @@
+use gstreamer as gst;
+use serde::Deserialize;
+
+use super::pipeline::{set_property_from_str, PipelineError};
+
+/// Audio capture element recorded alongside the video of a source. The
+/// `type` key uses the GStreamer element name.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum AudioSource {
+    /// ALSA capture device such as `hw:1`, the default device when unset.
+    #[serde(rename = "alsasrc")]
+    Alsa { device: Option<String> },
+    /// PulseAudio or PipeWire source, the default source when unset.
+    #[serde(rename = "pulsesrc")]
+    Pulse { device: Option<String> },
+    /// Synthetic tone, available everywhere GStreamer is.
+    #[serde(rename = "audiotestsrc")]
+    Test { wave: Option<String> },
+}
+
+impl AudioSource {
+    pub fn element_name(&self) -> &'static str {
+        match self {
+            AudioSource::Alsa { .. } => "alsasrc",
+            AudioSource::Pulse { .. } => "pulsesrc",
+            AudioSource::Test { .. } => "audiotestsrc",
+        }
+    }
+
+    /// Creates the capture element, producing raw audio.
+    pub fn build(&self) -> Result<gst::Element, PipelineError> {
+        let mut builder = gst::ElementFactory::make(self.element_name()).name("audio_source");
+        match self {
+            AudioSource::Alsa { device } | AudioSource::Pulse { device } => {
+                if let Some(device) = device {
+                    builder = builder.property("device", device);
+                }
+            }
+            AudioSource::Test { .. } => {
+                // Paced like the video sources, which are all live
+                builder = builder.property("is-live", true);
+            }
+        }
+
+        let source = builder.build().map_err(|err| {
+            PipelineError::ElementCreation(self.element_name().to_owned(), err.to_string())
+        })?;
+        if let AudioSource::Test { wave } = self {
+            set_property_from_str(&source, "wave", wave.as_deref().unwrap_or("sine"))?;
+        }
+
+        Ok(source)
+    }
+}
@@ -1 +1,15 @@
+pub mod audio_source;
+pub mod motion;
+pub mod output_path;
 pub mod pipeline;
+pub mod pre_roll;
+pub mod preview;
+pub mod recording_manager;
+pub mod rtsp_server;
+pub mod snapshot;
+pub mod stream_dto;
+pub mod stream_error;
+pub mod stream_routes;
+pub mod stream_service;
+pub mod thumbnails;
+pub mod video_source;
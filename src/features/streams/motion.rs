@@ -0,0 +1,317 @@
+use std::sync::{mpsc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use gstreamer as gst;
+use gstreamer::prelude::{ObjectExt, ToValue};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::configuration::MotionConfiguration;
+
+use super::pipeline::{make_element, PipelineError};
+
+/// Elements of the motion branch, fed with raw video.
+pub const MOTION_ELEMENTS: [&str; 6] = [
+    "queue",
+    "videoscale",
+    "videorate",
+    "videoconvert",
+    "capsfilter",
+    "appsink",
+];
+
+/// Part of the frame watched for motion, in fractions of the frame size so
+/// it does not depend on the capture resolution.
+#[derive(Clone, Debug, Deserialize)]
+pub struct MotionRegion {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl MotionRegion {
+    fn contains(&self, x: f64, y: f64) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+}
+
+/// Change of the motion state of a source.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MotionTransition {
+    /// `score` is the fraction of the watched pixels that changed.
+    Started { score: f64 },
+    /// No motion was seen for the cooldown.
+    Ended,
+}
+
+/// Motion of a source, published to event subscribers.
+#[derive(Clone, Debug)]
+pub struct MotionEvent {
+    pub source: String,
+    pub transition: MotionTransition,
+    pub at: DateTime<Utc>,
+    /// Recording started by the motion, when the source records on motion.
+    pub recording_id: Option<Uuid>,
+}
+
+/// Detects motion by differencing consecutive grayscale frames. A pixel
+/// changed when its luma moved by more than `threshold`, and a frame shows
+/// motion when the changed pixels cover `min_area` of the watched ones.
+pub struct MotionDetector {
+    threshold: u8,
+    min_area: f64,
+    cooldown: Duration,
+    regions: Vec<MotionRegion>,
+    previous: Vec<u8>,
+    /// Whether each pixel lies in a region, for frames of `mask_size`.
+    mask: Vec<bool>,
+    mask_size: (usize, usize),
+    /// Time of the latest motion while motion is ongoing.
+    last_motion: Option<Instant>,
+}
+
+impl MotionDetector {
+    pub fn new(configuration: &MotionConfiguration) -> Self {
+        Self {
+            threshold: configuration.threshold,
+            min_area: configuration.min_area,
+            cooldown: Duration::from_secs(configuration.cooldown),
+            regions: configuration.regions.clone(),
+            previous: Vec::new(),
+            mask: Vec::new(),
+            mask_size: (0, 0),
+            last_motion: None,
+        }
+    }
+
+    /// Compares a GRAY8 frame whose rows are `stride` bytes apart with the
+    /// previous one, returning the motion state change it causes.
+    pub fn process(
+        &mut self,
+        frame: &[u8],
+        width: usize,
+        height: usize,
+        stride: usize,
+        now: Instant,
+    ) -> Option<MotionTransition> {
+        let score = self.score(frame, width, height, stride)?;
+
+        match self.last_motion {
+            None if score >= self.min_area => {
+                self.last_motion = Some(now);
+                Some(MotionTransition::Started { score })
+            }
+            Some(_) if score >= self.min_area => {
+                self.last_motion = Some(now);
+                None
+            }
+            Some(last_motion) if now.duration_since(last_motion) >= self.cooldown => {
+                self.last_motion = None;
+                Some(MotionTransition::Ended)
+            }
+            _ => None,
+        }
+    }
+
+    /// Fraction of the watched pixels that changed since the previous frame,
+    /// `None` for the first frame of a size.
+    fn score(&mut self, frame: &[u8], width: usize, height: usize, stride: usize) -> Option<f64> {
+        if frame.len() < stride * height || stride < width {
+            return None;
+        }
+        if self.mask_size != (width, height) {
+            self.build_mask(width, height);
+            self.previous.clear();
+        }
+
+        let mut watched = 0usize;
+        let mut changed = 0usize;
+        let mut current = Vec::with_capacity(width * height);
+        for row in frame.chunks(stride).take(height) {
+            current.extend_from_slice(&row[..width]);
+        }
+        if self.previous.len() == current.len() {
+            for ((previous, pixel), watch) in self.previous.iter().zip(&current).zip(&self.mask) {
+                if *watch {
+                    watched += 1;
+                    if previous.abs_diff(*pixel) > self.threshold {
+                        changed += 1;
+                    }
+                }
+            }
+        }
+        let is_first = self.previous.is_empty();
+        self.previous = current;
+
+        match (is_first, watched) {
+            (true, _) => None,
+            (false, 0) => Some(0.0),
+            (false, _) => Some(changed as f64 / watched as f64),
+        }
+    }
+
+    fn build_mask(&mut self, width: usize, height: usize) {
+        self.mask = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                // Pixel centers, in fractions of the frame size
+                let x = (x as f64 + 0.5) / width as f64;
+                let y = (y as f64 + 0.5) / height as f64;
+                self.regions.is_empty() || self.regions.iter().any(|region| region.contains(x, y))
+            })
+            .collect();
+        self.mask_size = (width, height);
+    }
+}
+
+/// Motion branch of a live pipeline, reporting motion state changes on
+/// `transitions`.
+#[derive(Clone, Debug)]
+pub struct MotionOutput {
+    pub configuration: MotionConfiguration,
+    pub transitions: mpsc::Sender<MotionTransition>,
+}
+
+/// Creates the motion branch, analysing small grayscale frames:
+/// queue ! videoscale ! videorate ! videoconvert ! capsfilter ! appsink
+pub(super) fn build_motion_elements(
+    motion: &MotionOutput,
+) -> Result<Vec<gst::Element>, PipelineError> {
+    // Never hold back the recording when the analysis falls behind
+    let queue = gst::ElementFactory::make("queue")
+        .name("motion_queue")
+        .property("max-size-buffers", 1u32)
+        .property_from_str("leaky", "downstream")
+        .build()
+        .map_err(|err| PipelineError::ElementCreation("queue".into(), err.to_string()))?;
+    // Only the width is fixed, videoscale keeps the aspect ratio
+    let caps = gst::Caps::builder("video/x-raw")
+        .field("format", "GRAY8")
+        .field("width", motion.configuration.width as i32)
+        .field("pixel-aspect-ratio", gst::Fraction::new(1, 1))
+        .field(
+            "framerate",
+            gst::Fraction::new(motion.configuration.framerate as i32, 1),
+        )
+        .build();
+    let capsfilter = gst::ElementFactory::make("capsfilter")
+        .name("motion_caps")
+        .property("caps", caps)
+        .build()
+        .map_err(|err| PipelineError::ElementCreation("capsfilter".into(), err.to_string()))?;
+    let sink = gst::ElementFactory::make("appsink")
+        .name("motion_sink")
+        .property("emit-signals", true)
+        .property("max-buffers", 1u32)
+        .property("drop", true)
+        .property("sync", false)
+        .build()
+        .map_err(|err| PipelineError::ElementCreation("appsink".into(), err.to_string()))?;
+
+    let detector = Mutex::new(MotionDetector::new(&motion.configuration));
+    let transitions = motion.transitions.clone();
+    sink.connect("new-sample", false, move |args| {
+        let sink = args[0].get::<gst::Element>().ok()?;
+        let sample = sink.emit_by_name::<Option<gst::Sample>>("pull-sample", &[]);
+        let transition = sample
+            .as_ref()
+            .and_then(|sample| analyse_frame(&mut detector.lock().unwrap(), sample));
+        if let Some(transition) = transition {
+            // The receiver is gone once the manager stopped watching
+            let _ = transitions.send(transition);
+        }
+
+        Some(gst::FlowReturn::Ok.to_value())
+    });
+
+    Ok(vec![
+        queue,
+        make_element("videoscale", "motion_scale")?,
+        make_element("videorate", "motion_rate")?,
+        make_element("videoconvert", "motion_convert")?,
+        capsfilter,
+        sink,
+    ])
+}
+
+fn analyse_frame(detector: &mut MotionDetector, sample: &gst::Sample) -> Option<MotionTransition> {
+    let structure = sample.caps()?.structure(0)?;
+    let width = usize::try_from(structure.get::<i32>("width").ok()?).ok()?;
+    let height = usize::try_from(structure.get::<i32>("height").ok()?).ok()?;
+    let map = sample.buffer()?.map_readable().ok()?;
+    // Rows of raw video are padded to 4 bytes
+    let stride = map.len().checked_div(height)?;
+
+    detector.process(map.as_slice(), width, height, stride, Instant::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detector(regions: Vec<MotionRegion>) -> MotionDetector {
+        MotionDetector::new(&MotionConfiguration {
+            threshold: 25,
+            min_area: 0.1,
+            cooldown: 5,
+            regions,
+            record: false,
+            width: 4,
+            framerate: 5,
+        })
+    }
+
+    /// 4x4 frame, black but for its top left quarter set to `level`.
+    fn frame(level: u8) -> Vec<u8> {
+        let mut frame = vec![0u8; 16];
+        for index in [0, 1, 4, 5] {
+            frame[index] = level;
+        }
+        frame
+    }
+
+    #[test]
+    fn it_should_detect_motion_until_the_cooldown() {
+        let mut detector = detector(Vec::new());
+        let now = Instant::now();
+
+        assert_eq!(detector.process(&frame(0), 4, 4, 4, now), None);
+        assert_eq!(
+            detector.process(&frame(200), 4, 4, 4, now),
+            Some(MotionTransition::Started { score: 0.25 })
+        );
+        assert_eq!(
+            detector.process(&frame(200), 4, 4, 4, now + Duration::from_secs(4)),
+            None
+        );
+        assert_eq!(
+            detector.process(&frame(200), 4, 4, 4, now + Duration::from_secs(5)),
+            Some(MotionTransition::Ended)
+        );
+    }
+
+    #[test]
+    fn it_should_ignore_small_changes() {
+        let mut detector = detector(Vec::new());
+        let now = Instant::now();
+
+        detector.process(&frame(100), 4, 4, 4, now);
+        assert_eq!(detector.process(&frame(120), 4, 4, 4, now), None);
+    }
+
+    #[test]
+    fn it_should_only_watch_the_regions() {
+        let mut detector = detector(vec![MotionRegion {
+            x: 0.5,
+            y: 0.5,
+            width: 0.5,
+            height: 0.5,
+        }]);
+        let now = Instant::now();
+
+        detector.process(&frame(0), 4, 4, 4, now);
+        assert_eq!(detector.process(&frame(200), 4, 4, 4, now), None);
+    }
+}
@@ -0,0 +1,238 @@
+use std::fs::OpenOptions;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// Maximum number of suffixed names tried before giving up on a collision.
+const MAX_COLLISION_ATTEMPTS: u32 = 1000;
+
+/// Values available to `media.file_name_template`.
+pub struct TemplateContext<'a> {
+    pub id: Uuid,
+    pub source: &'a str,
+    pub extension: &'a str,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Renders a file name template such as `{source}/{date}/{id}.{extension}`.
+///
+/// Supported placeholders are `{id}`, `{source}`, `{date}` (`YYYY-MM-DD`),
+/// `{time}` (`HHMMSS`), `{timestamp}` (Unix seconds) and `{extension}`.
+/// The result must stay relative to the output folder.
+pub fn render_template(template: &str, context: &TemplateContext) -> io::Result<PathBuf> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .map(|end| start + end)
+            .ok_or_else(|| invalid_template(template, "unclosed placeholder"))?;
+
+        let value = match &rest[start + 1..end] {
+            "id" => context.id.to_string(),
+            "source" => sanitize_component(context.source),
+            "date" => context.timestamp.format("%Y-%m-%d").to_string(),
+            "time" => context.timestamp.format("%H%M%S").to_string(),
+            "timestamp" => context.timestamp.timestamp().to_string(),
+            "extension" => context.extension.to_owned(),
+            placeholder => {
+                return Err(invalid_template(
+                    template,
+                    &format!("unknown placeholder {{{}}}", placeholder),
+                ))
+            }
+        };
+        rendered.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    rendered.push_str(rest);
+
+    let path = PathBuf::from(rendered);
+    let is_relative = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    if !is_relative || path.file_name().is_none() {
+        return Err(invalid_template(
+            template,
+            "path must be relative to the output folder",
+        ));
+    }
+
+    Ok(path)
+}
+
+/// Resolves the template under `output_folder` and creates missing directories.
+pub fn resolve_output_path(
+    output_folder: &Path,
+    template: &str,
+    context: &TemplateContext,
+) -> io::Result<PathBuf> {
+    let location = output_folder.join(render_template(template, context)?);
+    if let Some(parent) = location.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    Ok(location)
+}
+
+/// Resolves the template and reserves the file, see `reserve_path`.
+pub fn allocate_output_path(
+    output_folder: &Path,
+    template: &str,
+    context: &TemplateContext,
+) -> io::Result<PathBuf> {
+    reserve_path(&resolve_output_path(output_folder, template, context)?)
+}
+
+/// Creates `location` so concurrent recordings never share a path. When the
+/// name is taken a `-1`, `-2`, ... suffix is added before the extension.
+pub fn reserve_path(location: &Path) -> io::Result<PathBuf> {
+    for attempt in 0..MAX_COLLISION_ATTEMPTS {
+        let candidate = match attempt {
+            0 => location.to_path_buf(),
+            _ => with_suffix(location, &attempt.to_string()),
+        };
+
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&candidate)
+        {
+            Ok(_) => return Ok(candidate),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("No free file name for {}", location.display()),
+    ))
+}
+
+/// Path of the `index`-th segment of a segmented recording at `location`.
+pub fn segment_path(location: &Path, index: u32) -> PathBuf {
+    with_suffix(location, &format!("{:05}", index))
+}
+
+/// Path of a file stored next to the recording at `location`, such as
+/// `<name>-poster.jpg`.
+pub fn companion_path(location: &Path, suffix: &str, extension: &str) -> PathBuf {
+    with_suffix(location, suffix).with_extension(extension)
+}
+
+fn with_suffix(location: &Path, suffix: &str) -> PathBuf {
+    let stem = location
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let file_name = match location.extension() {
+        Some(extension) => format!("{}-{}.{}", stem, suffix, extension.to_string_lossy()),
+        None => format!("{}-{}", stem, suffix),
+    };
+
+    location.with_file_name(file_name)
+}
+
+/// Keeps user-provided values from introducing extra path components.
+pub fn sanitize_component(value: &str) -> String {
+    let sanitized: String = value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    match sanitized.as_str() {
+        "" | "." | ".." => String::from("_"),
+        _ => sanitized,
+    }
+}
+
+fn invalid_template(template: &str, reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("Invalid file name template {}: {}", template, reason),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn context(id: Uuid, source: &str) -> TemplateContext<'_> {
+        TemplateContext {
+            id,
+            source,
+            extension: "mp4",
+            timestamp: Utc.with_ymd_and_hms(2025, 1, 8, 14, 30, 12).unwrap(),
+        }
+    }
+
+    #[test]
+    fn it_should_render_placeholders() {
+        let id = Uuid::new_v4();
+
+        let path = render_template(
+            "{source}/{date}/{time}-{id}.{extension}",
+            &context(id, "front-door"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            path,
+            PathBuf::from(format!("front-door/2025-01-08/143012-{}.mp4", id))
+        );
+    }
+
+    #[test]
+    fn it_should_reject_templates_escaping_the_output_folder() {
+        let id = Uuid::new_v4();
+
+        assert!(render_template("../{id}.mp4", &context(id, "default")).is_err());
+        assert!(render_template("/tmp/{id}.mp4", &context(id, "default")).is_err());
+        assert!(render_template("{unknown}.mp4", &context(id, "default")).is_err());
+        assert_eq!(
+            render_template("{source}/{id}.mp4", &context(id, "..")).unwrap(),
+            PathBuf::from(format!("_/{}.mp4", id))
+        );
+    }
+
+    #[test]
+    fn it_should_suffix_colliding_file_names() {
+        let output_folder = std::env::temp_dir().join(format!("capture-api-{}", Uuid::new_v4()));
+        let id = Uuid::new_v4();
+
+        let first =
+            allocate_output_path(&output_folder, "{source}.mp4", &context(id, "cam")).unwrap();
+        let second =
+            allocate_output_path(&output_folder, "{source}.mp4", &context(id, "cam")).unwrap();
+
+        assert_eq!(first, output_folder.join("cam.mp4"));
+        assert_eq!(second, output_folder.join("cam-1.mp4"));
+
+        std::fs::remove_dir_all(output_folder).unwrap();
+    }
+
+    #[test]
+    fn it_should_name_companion_files_after_the_recording() {
+        let location = PathBuf::from("output/cam/2025-01-08/143012.mp4");
+
+        assert_eq!(
+            companion_path(&location, "poster", "jpg"),
+            PathBuf::from("output/cam/2025-01-08/143012-poster.jpg")
+        );
+        assert_eq!(
+            companion_path(&location, "thumb-01", "jpg"),
+            PathBuf::from("output/cam/2025-01-08/143012-thumb-01.jpg")
+        );
+    }
+}
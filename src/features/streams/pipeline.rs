@@ -1,44 +1,888 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
 use gstreamer as gst;
-use gstreamer::prelude::ElementExt;
-use gstreamer::prelude::ElementExtManual;
-use gstreamer::prelude::GstObjectExt;
+use gstreamer::glib;
+use gstreamer::prelude::{
+    ElementExt, ElementExtManual, GstBinExtManual, GstObjectExt, GstValueExt, ObjectExt, PadExt,
+    ToValue,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-pub fn create_stream_pipeline() -> Result<(), gst::glib::error::Error> {
-    gst::init()?;
+use crate::configuration::AudioConfiguration;
 
-    let pipeline = gst::parse::launch(&format!(
-      "avfvideosrc ! videoconvert ! queue ! x264enc ! mp4mux ! filesink location=output/recording.mp4"
-    ))?;
+use super::output_path::{reserve_path, segment_path};
+use super::pre_roll::{build_pre_roll_input, PRE_ROLL_AUDIO_INPUT, PRE_ROLL_VIDEO_INPUT};
+use super::preview::{build_preview_elements, PreviewOutput, PREVIEW_ELEMENTS};
+use super::snapshot::{build_snapshot_tap, SNAPSHOT_TAP_ELEMENTS};
+use super::video_source::VideoSource;
 
-    pipeline
-        .set_state(gst::State::Playing)
-        .expect("Unable to set the pipeline to the `Playing` state");
-
-    // Wait for a short while to simulate some processing (e.g., 10 seconds)
-    std::thread::sleep(std::time::Duration::from_secs(10));
-
-    pipeline.send_event(gst::event::Eos::new());
-
-    for msg in pipeline.bus().unwrap().iter_timed(gst::ClockTime::NONE) {
-        use gst::MessageView;
-
-        match msg.view() {
-            MessageView::Eos(..) => break,
-            MessageView::Error(err) => {
-                panic!(
-                    "Error from {:?}: {} ({:?})",
-                    err.src().map(|s| s.path_string()),
-                    err.error(),
-                    err.debug()
-                );
+/// GStreamer element absent from the registry, with the plugin providing it.
+#[derive(Clone, Debug, Serialize)]
+pub struct MissingElement {
+    pub element: String,
+    pub plugin: String,
+}
+
+#[derive(Debug, Error)]
+pub enum PipelineError {
+    #[error("Missing GStreamer elements: {}", format_missing_elements(.0))]
+    MissingElements(Vec<MissingElement>),
+    #[error("Failed to create element {0}: {1}")]
+    ElementCreation(String, String),
+    #[error("Invalid value {2:?} for property {1} of {0}")]
+    InvalidProperty(String, String, String),
+    #[error("Failed to assemble pipeline: {0}")]
+    Assembly(String),
+    #[error("Failed to link {0} to {1}")]
+    Link(String, String),
+}
+
+fn format_missing_elements(missing: &[MissingElement]) -> String {
+    missing
+        .iter()
+        .map(|missing| format!("{} (from {})", missing.element, missing.plugin))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Plugin package shipping a GStreamer element, reported when it is missing.
+fn plugin_for_element(element: &str) -> &'static str {
+    match element {
+        "queue" | "tee" | "capsfilter" | "filesrc" | "filesink" => "gstreamer core",
+        "videoconvert" | "videoscale" | "videorate" | "videotestsrc" | "decodebin" | "appsink"
+        | "appsrc" | "alsasrc" | "audiotestsrc" | "audioconvert" | "audioresample" | "opusenc" => {
+            "gst-plugins-base"
+        }
+        "v4l2src" | "rtspsrc" | "rtph264pay" | "rtph265pay" | "rtpvp9pay" | "jpegenc"
+        | "pngenc" | "mp4mux" | "matroskamux" | "splitmuxsink" | "pulsesrc" | "aacparse" => {
+            "gst-plugins-good"
+        }
+        "h264parse" | "h265parse" | "vp9parse" | "av1parse" | "x265enc" | "av1enc" | "hlssink2"
+        | "mpegtsmux" | "opusparse" => "gst-plugins-bad",
+        "vp9enc" | "v4l2h264enc" => "gst-plugins-good",
+        "x264enc" => "gst-plugins-ugly",
+        "avenc_aac" => "gst-libav",
+        "rtpav1pay" => "gst-plugins-rs",
+        "libcamerasrc" => "libcamera",
+        _ => "unknown",
+    }
+}
+
+/// Video encoder of a recording, named after its GStreamer element.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+pub enum VideoEncoder {
+    #[default]
+    #[serde(rename = "x264enc")]
+    X264,
+    #[serde(rename = "x265enc")]
+    X265,
+    #[serde(rename = "vp9enc")]
+    Vp9,
+    #[serde(rename = "av1enc")]
+    Av1,
+    /// Hardware H.264 encoder of the Raspberry Pi.
+    #[serde(rename = "v4l2h264enc")]
+    V4l2H264,
+}
+
+impl VideoEncoder {
+    pub fn element_name(&self) -> &'static str {
+        match self {
+            VideoEncoder::X264 => "x264enc",
+            VideoEncoder::X265 => "x265enc",
+            VideoEncoder::Vp9 => "vp9enc",
+            VideoEncoder::Av1 => "av1enc",
+            VideoEncoder::V4l2H264 => "v4l2h264enc",
+        }
+    }
+
+    /// Parser normalizing the encoded stream before muxing.
+    pub fn parser_name(&self) -> &'static str {
+        match self {
+            VideoEncoder::X264 | VideoEncoder::V4l2H264 => "h264parse",
+            VideoEncoder::X265 => "h265parse",
+            VideoEncoder::Vp9 => "vp9parse",
+            VideoEncoder::Av1 => "av1parse",
+        }
+    }
+
+    /// RTP payloader of the encoded stream, used by the RTSP mounts.
+    pub fn payloader_name(&self) -> &'static str {
+        match self {
+            VideoEncoder::X264 | VideoEncoder::V4l2H264 => "rtph264pay",
+            VideoEncoder::X265 => "rtph265pay",
+            VideoEncoder::Vp9 => "rtpvp9pay",
+            VideoEncoder::Av1 => "rtpav1pay",
+        }
+    }
+
+    pub fn codec(&self) -> &'static str {
+        match self {
+            VideoEncoder::X264 | VideoEncoder::V4l2H264 => "h264",
+            VideoEncoder::X265 => "h265",
+            VideoEncoder::Vp9 => "vp9",
+            VideoEncoder::Av1 => "av1",
+        }
+    }
+
+    /// Whether the codec can be carried by the MPEG-TS segments of HLS.
+    pub fn supports_hls(&self) -> bool {
+        matches!(self.codec(), "h264" | "h265")
+    }
+}
+
+/// Named encoder settings, defined in `media.profiles` and selected per
+/// recording. Unset values keep the encoder defaults and the source format.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct EncodingProfile {
+    #[serde(default)]
+    pub encoder: VideoEncoder,
+    /// Target bitrate in kbit/s.
+    pub bitrate: Option<u32>,
+    /// Maximum number of frames between keyframes.
+    pub gop: Option<u32>,
+    /// Speed preset of x264enc and x265enc such as `veryfast`, `cpu-used`
+    /// of av1enc.
+    pub preset: Option<String>,
+    /// Tuning of x264enc and x265enc such as `zerolatency`.
+    pub tune: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub framerate: Option<u32>,
+}
+
+impl EncodingProfile {
+    /// Checks the settings against the encoder, failing on values it would
+    /// reject. Only the bitrate is checked when the encoder is not installed.
+    pub fn validate(&self) -> Result<(), PipelineError> {
+        encoder_properties(self)?;
+        if gst::init().is_err() || gst::ElementFactory::find(self.encoder.element_name()).is_none()
+        {
+            return Ok(());
+        }
+
+        build_encoder(self).map(|_| ())
+    }
+
+    /// Whether the raw video is scaled or resampled before encoding.
+    pub(super) fn converts_format(&self) -> bool {
+        self.width.is_some() || self.height.is_some() || self.framerate.is_some()
+    }
+}
+
+/// Audio codec of the sources recording sound, configured per source.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioEncoder {
+    #[default]
+    Opus,
+    Aac,
+}
+
+impl AudioEncoder {
+    pub fn element_name(&self) -> &'static str {
+        match self {
+            AudioEncoder::Opus => "opusenc",
+            AudioEncoder::Aac => "avenc_aac",
+        }
+    }
+
+    /// Parser framing the encoded stream before muxing.
+    pub fn parser_name(&self) -> &'static str {
+        match self {
+            AudioEncoder::Opus => "opusparse",
+            AudioEncoder::Aac => "aacparse",
+        }
+    }
+
+    pub fn codec(&self) -> &'static str {
+        match self {
+            AudioEncoder::Opus => "opus",
+            AudioEncoder::Aac => "aac",
+        }
+    }
+}
+
+/// Interval at which fragmented MP4 output is flushed to disk.
+const FRAGMENT_DURATION: Duration = Duration::from_secs(1);
+
+/// Output container, configured with `media.container`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Container {
+    /// Regular MP4, only playable once the recording reached EOS.
+    #[default]
+    Mp4,
+    /// MP4 written as self-contained fragments, playable up to the last
+    /// complete fragment when the process is killed.
+    FragmentedMp4,
+    /// Matroska, playable up to the last written cluster.
+    Matroska,
+}
+
+impl Container {
+    pub fn element_name(&self) -> &'static str {
+        match self {
+            Container::Mp4 | Container::FragmentedMp4 => "mp4mux",
+            Container::Matroska => "matroskamux",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Container::Mp4 => "mp4",
+            Container::FragmentedMp4 => "fragmented_mp4",
+            Container::Matroska => "matroska",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Container::Mp4 | Container::FragmentedMp4 => "mp4",
+            Container::Matroska => "mkv",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Container::Mp4 | Container::FragmentedMp4 => "video/mp4",
+            Container::Matroska => "video/x-matroska",
+        }
+    }
+
+    /// Whether a file cut short before EOS can still be played.
+    pub fn is_crash_safe(&self) -> bool {
+        !matches!(self, Container::Mp4)
+    }
+}
+
+impl FromStr for Container {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "mp4" => Ok(Container::Mp4),
+            "fragmented_mp4" => Ok(Container::FragmentedMp4),
+            "matroska" => Ok(Container::Matroska),
+            _ => Err(format!("Unknown container: {}", value)),
+        }
+    }
+}
+
+/// File rotation of a continuous recording, a new segment starts when
+/// either limit is reached.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SegmentPolicy {
+    pub max_duration: Option<Duration>,
+    pub max_size_bytes: Option<u64>,
+}
+
+/// Live HLS branch writing `playlist.m3u8` and its segments to `directory`.
+#[derive(Clone, Debug)]
+pub struct HlsOutput {
+    pub directory: PathBuf,
+    pub target_duration: u32,
+    pub playlist_length: u32,
+    pub max_files: u32,
+}
+
+impl HlsOutput {
+    fn playlist_location(&self) -> PathBuf {
+        self.directory.join("playlist.m3u8")
+    }
+
+    fn segment_location(&self) -> PathBuf {
+        self.directory.join("segment%05d.ts")
+    }
+}
+
+/// Typed description of a recording pipeline:
+/// source ! videoconvert ! [videoscale ! videorate ! capsfilter] ! queue ! encoder ! parser ! muxer ! filesink
+/// audio_source ! audioconvert ! audioresample ! queue ! encoder ! parser ! muxer
+///
+/// Recordings of a source with a pre-roll buffer take its encoded streams
+/// instead, see `pre_roll`:
+/// appsrc ! parser ! muxer ! filesink
+///
+/// Segmented recordings replace the muxer and filesink with a splitmuxsink
+/// writing numbered files next to `location`. With an HLS output the parser
+/// feeds a tee, whose second branch ends in a hlssink2. With a preview output
+/// the raw video is also teed to a JPEG encoder, see `preview`, and the
+/// snapshot tap keeps the latest raw frame, see `snapshot`. Audio is only
+/// recorded, the live outputs carry the video alone.
+#[derive(Clone, Debug)]
+pub struct PipelineSpec {
+    pub source: VideoSource,
+    pub audio: Option<AudioConfiguration>,
+    pub profile: EncodingProfile,
+    pub container: Container,
+    pub location: PathBuf,
+    pub segments: Option<SegmentPolicy>,
+    pub hls: Option<HlsOutput>,
+    pub preview: Option<PreviewOutput>,
+    /// Keeps the latest raw frame available for snapshots.
+    pub snapshot_tap: bool,
+    /// Reads the encoded streams from the pre-roll buffer of the source
+    /// rather than capturing them, `source` is then unused.
+    pub pre_roll: bool,
+}
+
+impl PipelineSpec {
+    pub fn required_elements(&self) -> Vec<&'static str> {
+        let mut elements = match self.pre_roll {
+            true => vec!["appsrc"],
+            false => capture_element_names(
+                &self.source,
+                &self.profile,
+                self.preview.is_some(),
+                self.snapshot_tap,
+            ),
+        };
+        elements.extend([
+            self.profile.encoder.parser_name(),
+            self.container.element_name(),
+        ]);
+        elements.push(match self.segments {
+            Some(_) => "splitmuxsink",
+            None => "filesink",
+        });
+        if let Some(audio) = &self.audio {
+            if !self.pre_roll {
+                elements.extend(audio_capture_element_names(audio));
+            }
+            elements.push(audio.encoder.parser_name());
+        }
+        if self.hls.is_some() {
+            elements.extend(["tee", "hlssink2", "mpegtsmux"]);
+        }
+
+        elements
+    }
+
+    /// Checks every required element against the GStreamer registry.
+    pub fn validate(&self) -> Result<(), PipelineError> {
+        check_elements(&self.required_elements())
+    }
+}
+
+/// Elements capturing and encoding the video of `source`, up to the encoder.
+pub(super) fn capture_element_names(
+    source: &VideoSource,
+    profile: &EncodingProfile,
+    preview: bool,
+    snapshot_tap: bool,
+) -> Vec<&'static str> {
+    let mut elements = source.element_names();
+    elements.extend(["videoconvert", "queue", profile.encoder.element_name()]);
+    if profile.converts_format() {
+        elements.extend(["videoscale", "videorate", "capsfilter"]);
+    }
+    if preview || snapshot_tap {
+        elements.push("tee");
+    }
+    if preview {
+        elements.extend(PREVIEW_ELEMENTS);
+    }
+    if snapshot_tap {
+        elements.extend(SNAPSHOT_TAP_ELEMENTS);
+    }
+
+    elements
+}
+
+/// Elements capturing and encoding the audio of a source, up to the encoder.
+pub(super) fn audio_capture_element_names(audio: &AudioConfiguration) -> [&'static str; 4] {
+    [
+        audio.source.element_name(),
+        "audioconvert",
+        "audioresample",
+        audio.encoder.element_name(),
+    ]
+}
+
+/// Fails with every element of `elements` absent from the GStreamer registry.
+pub(super) fn check_elements(elements: &[&str]) -> Result<(), PipelineError> {
+    let mut missing: Vec<MissingElement> = elements
+        .iter()
+        .filter(|element| gst::ElementFactory::find(element).is_none())
+        .map(|element| MissingElement {
+            element: (*element).to_owned(),
+            plugin: plugin_for_element(element).to_owned(),
+        })
+        .collect();
+    missing.sort_by(|a, b| a.element.cmp(&b.element));
+    missing.dedup_by(|a, b| a.element == b.element);
+
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(PipelineError::MissingElements(missing))
+    }
+}
+
+/// Assembles the pipeline described by `spec`, failing before any element is
+/// created when one of them is not available.
+pub fn build_pipeline(spec: &PipelineSpec) -> Result<gst::Pipeline, PipelineError> {
+    spec.validate()?;
+
+    let pipeline = gst::Pipeline::new();
+
+    let parser = make_element(spec.profile.encoder.parser_name(), "parser")?;
+    let muxer = build_muxer(spec.container)?;
+
+    let record_elements = match spec.segments {
+        Some(segments) => vec![build_splitmuxsink(spec, segments, muxer)?],
+        None => {
+            let sink = gst::ElementFactory::make("filesink")
+                .name("sink")
+                .property("location", spec.location.display().to_string())
+                .build()
+                .map_err(|err| {
+                    PipelineError::ElementCreation("filesink".into(), err.to_string())
+                })?;
+            vec![muxer, sink]
+        }
+    };
+
+    // Each branch starts with the tee it is fed from
+    let (mut elements, mut branches) = match spec.pre_roll {
+        true => (
+            vec![build_pre_roll_input(PRE_ROLL_VIDEO_INPUT)?],
+            Vec::new(),
+        ),
+        false => build_capture_elements(&spec.profile, spec.preview.as_ref(), spec.snapshot_tap)?,
+    };
+    let first_element = elements[0].clone();
+    elements.push(parser.clone());
+    if let Some(hls) = &spec.hls {
+        // Repeat the stream headers so every HLS segment can be decoded
+        parser.set_property("config-interval", -1i32);
+
+        let tee = make_element("tee", "tee")?;
+        branches.push(vec![
+            tee.clone(),
+            make_element("queue", "hls_queue")?,
+            build_hlssink(hls)?,
+        ]);
+        elements.extend([tee, make_element("queue", "record_queue")?]);
+    }
+    // The audio branch ends in the muxer, which splitmuxsink wraps
+    let audio_target = record_elements[0].clone();
+    elements.extend(record_elements);
+
+    add_chain(&pipeline, &elements)?;
+    add_branches(&pipeline, &branches)?;
+    if let Some(audio) = &spec.audio {
+        let mut audio_elements = match spec.pre_roll {
+            true => vec![build_pre_roll_input(PRE_ROLL_AUDIO_INPUT)?],
+            false => build_audio_capture_elements(audio)?,
+        };
+        audio_elements.push(make_element(audio.encoder.parser_name(), "audio_parser")?);
+        add_audio_branch(&pipeline, &audio_elements, &audio_target)?;
+    }
+
+    if !spec.pre_roll {
+        spec.source.build(&pipeline, &first_element)?;
+    }
+
+    Ok(pipeline)
+}
+
+/// Creates the elements turning raw video into the encoded stream of
+/// `profile`, along with the live output branches fed from a raw tee:
+/// videoconvert ! [tee] ! [videoscale ! videorate ! capsfilter] ! queue ! encoder
+///
+/// The video source links to the first element.
+pub(super) fn build_capture_elements(
+    profile: &EncodingProfile,
+    preview: Option<&PreviewOutput>,
+    snapshot_tap: bool,
+) -> Result<(Vec<gst::Element>, Vec<Vec<gst::Element>>), PipelineError> {
+    let mut branches = Vec::new();
+    let mut elements = vec![make_element("videoconvert", "convert")?];
+    if preview.is_some() || snapshot_tap {
+        let raw_tee = make_element("tee", "raw_tee")?;
+        branches.extend(build_raw_branches(&raw_tee, preview, snapshot_tap)?);
+        elements.push(raw_tee);
+    }
+    if profile.converts_format() {
+        elements.extend(build_format_elements(profile)?);
+    }
+    elements.extend([make_element("queue", "queue")?, build_encoder(profile)?]);
+
+    Ok((elements, branches))
+}
+
+/// Properties of the video encoder configured by `profile`, as strings.
+/// Settings an encoder has no property for are left out.
+fn encoder_properties(
+    profile: &EncodingProfile,
+) -> Result<Vec<(&'static str, String)>, PipelineError> {
+    let encoder = profile.encoder.element_name();
+    let mut properties: Vec<(&str, String)> = Vec::new();
+    match profile.encoder {
+        VideoEncoder::X264 | VideoEncoder::X265 => {
+            properties.extend(
+                profile
+                    .bitrate
+                    .map(|bitrate| ("bitrate", bitrate.to_string())),
+            );
+            properties.extend(profile.gop.map(|gop| ("key-int-max", gop.to_string())));
+            properties.extend(
+                profile
+                    .preset
+                    .clone()
+                    .map(|preset| ("speed-preset", preset)),
+            );
+            properties.extend(profile.tune.clone().map(|tune| ("tune", tune)));
+        }
+        VideoEncoder::Vp9 => {
+            if let Some(bitrate) = profile.bitrate {
+                let bitrate = bits_per_second(encoder, "target-bitrate", bitrate)?;
+                properties.push(("target-bitrate", bitrate.to_string()));
+            }
+            properties.extend(
+                profile
+                    .gop
+                    .map(|gop| ("keyframe-max-dist", gop.to_string())),
+            );
+            // Encode in real time, the default deadline is far too slow
+            properties.push(("deadline", String::from("1")));
+        }
+        VideoEncoder::Av1 => {
+            properties.extend(
+                profile
+                    .bitrate
+                    .map(|bitrate| ("target-bitrate", bitrate.to_string())),
+            );
+            properties.extend(
+                profile
+                    .gop
+                    .map(|gop| ("keyframe-max-dist", gop.to_string())),
+            );
+            properties.extend(profile.preset.clone().map(|preset| ("cpu-used", preset)));
+        }
+        VideoEncoder::V4l2H264 => {
+            // The V4L2 driver takes its settings as controls
+            let mut controls = vec![String::from("controls")];
+            if let Some(bitrate) = profile.bitrate {
+                let bitrate = bits_per_second(encoder, "extra-controls", bitrate)?;
+                controls.push(format!("video_bitrate={}", bitrate));
+            }
+            controls.extend(
+                profile
+                    .gop
+                    .map(|gop| format!("h264_i_frame_period={}", gop)),
+            );
+            if controls.len() > 1 {
+                properties.push(("extra-controls", controls.join(",")));
             }
-            _ => (),
         }
     }
 
+    Ok(properties)
+}
+
+/// Converts a bitrate in kbit/s to bit/s, failing when it overflows.
+fn bits_per_second(element: &str, property: &str, bitrate: u32) -> Result<u32, PipelineError> {
+    bitrate.checked_mul(1000).ok_or_else(|| {
+        PipelineError::InvalidProperty(element.to_owned(), property.to_owned(), bitrate.to_string())
+    })
+}
+
+/// Creates the video encoder configured by `profile`.
+fn build_encoder(profile: &EncodingProfile) -> Result<gst::Element, PipelineError> {
+    let encoder = make_element(profile.encoder.element_name(), "encoder")?;
+    for (name, value) in encoder_properties(profile)? {
+        set_property_from_str(&encoder, name, &value)?;
+    }
+
+    Ok(encoder)
+}
+
+/// Launch description of the video encoder configured by `profile`, such as
+/// `x264enc bitrate="2000" tune="zerolatency"`.
+pub(super) fn encoder_launch_fragment(profile: &EncodingProfile) -> Result<String, PipelineError> {
+    let mut fragment = String::from(profile.encoder.element_name());
+    for (name, value) in encoder_properties(profile)? {
+        let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+        fragment.push_str(&format!(" {}=\"{}\"", name, value));
+    }
+
+    Ok(fragment)
+}
+
+/// Scales and resamples the raw video to the format of `profile`:
+/// videoscale ! videorate ! capsfilter
+fn build_format_elements(profile: &EncodingProfile) -> Result<Vec<gst::Element>, PipelineError> {
+    let capsfilter = gst::ElementFactory::make("capsfilter")
+        .name("format_caps")
+        .property("caps", format_caps(profile))
+        .build()
+        .map_err(|err| PipelineError::ElementCreation("capsfilter".into(), err.to_string()))?;
+
+    Ok(vec![
+        make_element("videoscale", "format_scale")?,
+        make_element("videorate", "format_rate")?,
+        capsfilter,
+    ])
+}
+
+/// Raw video format of `profile`, fields it leaves unset are not constrained.
+pub(super) fn format_caps(profile: &EncodingProfile) -> gst::Caps {
+    let mut caps = gst::Caps::builder("video/x-raw");
+    if let Some(width) = profile.width {
+        caps = caps.field("width", width as i32);
+    }
+    if let Some(height) = profile.height {
+        caps = caps.field("height", height as i32);
+    }
+    if let Some(framerate) = profile.framerate {
+        caps = caps.field("framerate", gst::Fraction::new(framerate as i32, 1));
+    }
+
+    caps.build()
+}
+
+/// Creates the elements capturing and encoding audio:
+/// audio_source ! audioconvert ! audioresample ! queue ! encoder
+pub(super) fn build_audio_capture_elements(
+    audio: &AudioConfiguration,
+) -> Result<Vec<gst::Element>, PipelineError> {
+    let encoder = make_element(audio.encoder.element_name(), "audio_encoder")?;
+    if let Some(bitrate) = audio.bitrate {
+        let bitrate = bits_per_second(audio.encoder.element_name(), "bitrate", bitrate)?;
+        // The bitrate property type differs between encoders
+        set_property_from_str(&encoder, "bitrate", &bitrate.to_string())?;
+    }
+
+    Ok(vec![
+        audio.source.build()?,
+        make_element("audioconvert", "audio_convert")?,
+        make_element("audioresample", "audio_resample")?,
+        make_element("queue", "audio_queue")?,
+        encoder,
+    ])
+}
+
+/// Adds the audio branch `elements` and links its last element to an
+/// `audio_%u` pad of `target`.
+fn add_audio_branch(
+    pipeline: &gst::Pipeline,
+    elements: &[gst::Element],
+    target: &gst::Element,
+) -> Result<(), PipelineError> {
+    add_chain(pipeline, elements)?;
+
+    let last = &elements[elements.len() - 1];
+    let link_error = || PipelineError::Link(last.name().into(), target.name().into());
+    let sink_pad = target
+        .request_pad_simple("audio_%u")
+        .ok_or_else(link_error)?;
+    last.static_pad("src")
+        .ok_or_else(link_error)?
+        .link(&sink_pad)
+        .map_err(|_| link_error())?;
+
+    Ok(())
+}
+
+/// Branches fed with raw video by `raw_tee`, each starting with the tee.
+pub(super) fn build_raw_branches(
+    raw_tee: &gst::Element,
+    preview: Option<&PreviewOutput>,
+    snapshot_tap: bool,
+) -> Result<Vec<Vec<gst::Element>>, PipelineError> {
+    let mut branches = Vec::new();
+    if let Some(preview) = preview {
+        let mut branch = vec![raw_tee.clone()];
+        branch.extend(build_preview_elements(preview)?);
+        branches.push(branch);
+    }
+    if snapshot_tap {
+        let mut branch = vec![raw_tee.clone()];
+        branch.extend(build_snapshot_tap()?);
+        branches.push(branch);
+    }
+
+    Ok(branches)
+}
+
+/// Adds the elements of `branches` after their tee, which is already part of
+/// the pipeline.
+pub(super) fn add_branches(
+    pipeline: &gst::Pipeline,
+    branches: &[Vec<gst::Element>],
+) -> Result<(), PipelineError> {
+    for branch in branches {
+        add_chain(pipeline, &branch[1..])?;
+        link_elements(&[&branch[0], &branch[1]])?;
+    }
+
+    Ok(())
+}
+
+/// Adds `elements` to the pipeline and links them in order.
+pub(super) fn add_chain(
+    pipeline: &gst::Pipeline,
+    elements: &[gst::Element],
+) -> Result<(), PipelineError> {
+    let elements: Vec<&gst::Element> = elements.iter().collect();
     pipeline
-        .set_state(gst::State::Null)
-        .expect("Unable to set the pipeline to the `Null` state");
+        .add_many(&elements)
+        .map_err(|err| PipelineError::Assembly(err.to_string()))?;
+
+    link_elements(&elements)
+}
+
+fn build_hlssink(hls: &HlsOutput) -> Result<gst::Element, PipelineError> {
+    gst::ElementFactory::make("hlssink2")
+        .name("hls_sink")
+        .property("location", hls.segment_location().display().to_string())
+        .property(
+            "playlist-location",
+            hls.playlist_location().display().to_string(),
+        )
+        .property("target-duration", hls.target_duration)
+        .property("playlist-length", hls.playlist_length)
+        .property("max-files", hls.max_files)
+        .build()
+        .map_err(|err| PipelineError::ElementCreation("hlssink2".into(), err.to_string()))
+}
+
+fn build_muxer(container: Container) -> Result<gst::Element, PipelineError> {
+    let mut builder = gst::ElementFactory::make(container.element_name()).name("muxer");
+    if container == Container::FragmentedMp4 {
+        builder = builder.property("fragment-duration", FRAGMENT_DURATION.as_millis() as u32);
+    }
+
+    builder.build().map_err(|err| {
+        PipelineError::ElementCreation(container.element_name().to_owned(), err.to_string())
+    })
+}
+
+/// Creates a splitmuxsink rotating files according to `segments`. Each
+/// segment file is reserved like a regular recording, next to `spec.location`.
+fn build_splitmuxsink(
+    spec: &PipelineSpec,
+    segments: SegmentPolicy,
+    muxer: gst::Element,
+) -> Result<gst::Element, PipelineError> {
+    let max_size_time = segments
+        .max_duration
+        .map(|duration| u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX))
+        .unwrap_or(0);
+    let sink = gst::ElementFactory::make("splitmuxsink")
+        .name("sink")
+        .property("muxer", muxer)
+        .property("max-size-time", max_size_time)
+        .property("max-size-bytes", segments.max_size_bytes.unwrap_or(0))
+        // Ask the encoder for a keyframe so segments split on time
+        .property("send-keyframe-requests", max_size_time > 0)
+        .build()
+        .map_err(|err| PipelineError::ElementCreation("splitmuxsink".into(), err.to_string()))?;
+
+    let location = spec.location.clone();
+    sink.connect("format-location-full", false, move |args| {
+        let index = args[1].get::<u32>().unwrap_or_default();
+        let path = reserve_path(&segment_path(&location, index)).unwrap_or_else(|err| {
+            tracing::warn!("Failed to reserve segment {}: {}", index, err);
+            segment_path(&location, index)
+        });
+
+        Some(path.display().to_string().to_value())
+    });
+
+    Ok(sink)
+}
+
+pub(super) fn make_element(factory: &str, name: &str) -> Result<gst::Element, PipelineError> {
+    gst::ElementFactory::make(factory)
+        .name(name)
+        .build()
+        .map_err(|err| PipelineError::ElementCreation(factory.to_owned(), err.to_string()))
+}
+
+/// Sets a property from its string form like `property_from_str`, but fails
+/// instead of panicking when the element has no such writable property or
+/// the value cannot be parsed or is out of range.
+pub(super) fn set_property_from_str(
+    element: &gst::Element,
+    name: &str,
+    value: &str,
+) -> Result<(), PipelineError> {
+    let invalid = || {
+        let factory = element
+            .factory()
+            .map(|factory| factory.name().to_string())
+            .unwrap_or_else(|| element.name().to_string());
+        PipelineError::InvalidProperty(factory, name.to_owned(), value.to_owned())
+    };
+
+    let pspec = element
+        .find_property(name)
+        .filter(|pspec| {
+            let flags = pspec.flags();
+            flags.contains(glib::ParamFlags::WRITABLE)
+                && !flags.contains(glib::ParamFlags::CONSTRUCT_ONLY)
+        })
+        .ok_or_else(invalid)?;
+    let parsed = glib::Value::deserialize(value, pspec.value_type())
+        .ok()
+        .filter(|parsed| is_in_range(&pspec, parsed))
+        .ok_or_else(invalid)?;
+    element.set_property_from_value(name, &parsed);
+
+    Ok(())
+}
+
+/// Whether a numeric value lies within the bounds of its property, setting
+/// it panics otherwise.
+fn is_in_range(pspec: &glib::ParamSpec, value: &glib::Value) -> bool {
+    if let Some(pspec) = pspec.downcast_ref::<glib::ParamSpecUInt>() {
+        return value
+            .get::<u32>()
+            .is_ok_and(|value| (pspec.minimum()..=pspec.maximum()).contains(&value));
+    }
+    if let Some(pspec) = pspec.downcast_ref::<glib::ParamSpecInt>() {
+        return value
+            .get::<i32>()
+            .is_ok_and(|value| (pspec.minimum()..=pspec.maximum()).contains(&value));
+    }
+    if let Some(pspec) = pspec.downcast_ref::<glib::ParamSpecUInt64>() {
+        return value
+            .get::<u64>()
+            .is_ok_and(|value| (pspec.minimum()..=pspec.maximum()).contains(&value));
+    }
+    if let Some(pspec) = pspec.downcast_ref::<glib::ParamSpecInt64>() {
+        return value
+            .get::<i64>()
+            .is_ok_and(|value| (pspec.minimum()..=pspec.maximum()).contains(&value));
+    }
+    if let Some(pspec) = pspec.downcast_ref::<glib::ParamSpecDouble>() {
+        return value
+            .get::<f64>()
+            .is_ok_and(|value| (pspec.minimum()..=pspec.maximum()).contains(&value));
+    }
+    if let Some(pspec) = pspec.downcast_ref::<glib::ParamSpecFloat>() {
+        return value
+            .get::<f32>()
+            .is_ok_and(|value| (pspec.minimum()..=pspec.maximum()).contains(&value));
+    }
+
+    true
+}
+
+/// Links `elements` in order, reporting the first pair that cannot be linked.
+pub(super) fn link_elements(elements: &[&gst::Element]) -> Result<(), PipelineError> {
+    for pair in elements.windows(2) {
+        pair[0]
+            .link(pair[1])
+            .map_err(|_| PipelineError::Link(pair[0].name().into(), pair[1].name().into()))?;
+    }
 
     Ok(())
 }
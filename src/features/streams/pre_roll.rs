@@ -0,0 +1,399 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use gstreamer as gst;
+use gstreamer::prelude::{ElementExt, GstBinExt, ObjectExt, ToValue};
+use uuid::Uuid;
+
+use crate::configuration::SourceConfiguration;
+
+use super::motion::{build_motion_elements, MotionOutput, MOTION_ELEMENTS};
+use super::pipeline::{
+    add_branches, add_chain, audio_capture_element_names, build_audio_capture_elements,
+    build_capture_elements, capture_element_names, check_elements, make_element, EncodingProfile,
+    PipelineError,
+};
+use super::preview::PreviewOutput;
+use super::recording_manager::RecordingManagerError;
+use super::stream_error::StreamError;
+
+/// Name of the appsrc feeding a recording with the buffered video.
+pub const PRE_ROLL_VIDEO_INPUT: &str = "video_input";
+
+/// Name of the appsrc feeding a recording with the buffered audio.
+pub const PRE_ROLL_AUDIO_INPUT: &str = "audio_input";
+
+/// Frames of a stream covering at least `duration`, always starting with a
+/// keyframe so a decoder can begin with the first one.
+struct FrameRing<T> {
+    duration: Duration,
+    /// Timestamp, keyframe flag and content of each frame.
+    frames: VecDeque<(Duration, bool, T)>,
+}
+
+impl<T> FrameRing<T> {
+    fn new(duration: Duration) -> Self {
+        Self {
+            duration,
+            frames: VecDeque::new(),
+        }
+    }
+
+    /// Appends a frame, then drops the oldest group of frames for as long as
+    /// the remaining ones still cover the duration.
+    fn push(&mut self, time: Duration, keyframe: bool, frame: T) {
+        if self.frames.is_empty() && !keyframe {
+            return;
+        }
+        self.frames.push_back((time, keyframe, frame));
+
+        while let Some(next) = self
+            .frames
+            .iter()
+            .skip(1)
+            .position(|(_, keyframe, _)| *keyframe)
+        {
+            let next = next + 1;
+            if time.saturating_sub(self.frames[next].0) < self.duration {
+                break;
+            }
+            self.frames.drain(..next);
+        }
+    }
+
+    /// Drops the frames older than `time`, only meant for streams made of
+    /// keyframes such as audio.
+    fn trim_before(&mut self, time: Duration) {
+        while self
+            .frames
+            .front()
+            .is_some_and(|(front, _, _)| *front < time)
+        {
+            self.frames.pop_front();
+        }
+    }
+
+    /// Timestamp of the oldest frame.
+    fn start(&self) -> Option<Duration> {
+        self.frames.front().map(|(time, _, _)| *time)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (Duration, &T)> {
+        self.frames.iter().map(|(time, _, frame)| (*time, frame))
+    }
+}
+
+/// Recording fed by the buffer.
+struct PreRollOutput {
+    id: Uuid,
+    video: gst::Element,
+    audio: Option<gst::Element>,
+    /// Timestamp of the first frame sent, subtracted from every timestamp so
+    /// the recording starts at zero. Unset until a keyframe arrived.
+    start: Option<Duration>,
+}
+
+struct PreRollState {
+    video: FrameRing<gst::Sample>,
+    audio: FrameRing<gst::Sample>,
+    outputs: Vec<PreRollOutput>,
+}
+
+impl PreRollState {
+    fn push_video(&mut self, sample: gst::Sample) {
+        let Some((time, keyframe)) = frame_info(&sample) else {
+            return;
+        };
+
+        for output in &mut self.outputs {
+            // Outputs attached while the buffer was empty begin with a keyframe
+            if output.start.is_none() && keyframe {
+                output.start = Some(time);
+            }
+            if let Some(start) = output.start {
+                push_frame(&output.video, &sample, start);
+            }
+        }
+
+        self.video.push(time, keyframe, sample);
+        // Audio preceding the first keyframe would never be recorded
+        if let Some(start) = self.video.start() {
+            self.audio.trim_before(start);
+        }
+    }
+
+    fn push_audio(&mut self, sample: gst::Sample) {
+        let Some((time, _)) = frame_info(&sample) else {
+            return;
+        };
+
+        for output in &self.outputs {
+            if let (Some(input), Some(start)) = (&output.audio, output.start) {
+                if time >= start {
+                    push_frame(input, &sample, start);
+                }
+            }
+        }
+
+        self.audio.push(time, true, sample);
+    }
+}
+
+/// Encoded video of a source, and its audio when recorded, kept for the last
+/// seconds so recordings of the source start before they were requested:
+/// source ! videoconvert ! tee ! queue ! encoder ! parser ! appsink
+/// audio_source ! audioconvert ! audioresample ! queue ! encoder ! parser ! appsink
+///
+/// The buffer holds the capture device for as long as it runs, so it also
+/// feeds the preview, snapshot tap and motion detection of the source. Recordings attach their
+/// appsrcs, see `build_pre_roll_input`, and receive the buffered frames
+/// followed by the live ones.
+pub struct PreRollBuffer {
+    pub profile: EncodingProfile,
+    pipeline: gst::Pipeline,
+    state: Arc<Mutex<PreRollState>>,
+}
+
+impl PreRollBuffer {
+    /// Builds and plays the buffer pipeline of `source`, keeping `duration`
+    /// of video encoded with `profile`. A zero duration keeps the frames
+    /// since the latest keyframe.
+    pub(super) fn start(
+        source: &SourceConfiguration,
+        duration: Duration,
+        profile: EncodingProfile,
+        preview: Option<&PreviewOutput>,
+        motion: Option<&MotionOutput>,
+    ) -> Result<Self, RecordingManagerError> {
+        let mut elements = capture_element_names(&source.video, &profile, preview.is_some(), true);
+        elements.extend([profile.encoder.parser_name(), "appsink"]);
+        if motion.is_some() {
+            elements.extend(MOTION_ELEMENTS);
+        }
+        if let Some(audio) = &source.audio {
+            elements.extend(audio_capture_element_names(audio));
+            elements.push(audio.encoder.parser_name());
+        }
+        check_elements(&elements)?;
+
+        let state = Arc::new(Mutex::new(PreRollState {
+            video: FrameRing::new(duration),
+            audio: FrameRing::new(duration),
+            outputs: Vec::new(),
+        }));
+
+        let pipeline = gst::Pipeline::new();
+        let (mut elements, mut branches) = build_capture_elements(&profile, preview, true)?;
+        if let Some(motion) = motion {
+            // Fed by the raw tee of the snapshot tap, every buffer has one
+            let mut branch = vec![branches[0][0].clone()];
+            branch.extend(build_motion_elements(motion)?);
+            branches.push(branch);
+        }
+        let convert = elements[0].clone();
+        let parser = make_element(profile.encoder.parser_name(), "parser")?;
+        // Repeat the stream headers so recordings can start at any keyframe
+        if parser.find_property("config-interval").is_some() {
+            parser.set_property("config-interval", -1i32);
+        }
+        elements.extend([
+            parser,
+            build_ring_sink("video_ring", &state, PreRollState::push_video)?,
+        ]);
+        add_chain(&pipeline, &elements)?;
+        add_branches(&pipeline, &branches)?;
+
+        if let Some(audio) = &source.audio {
+            let mut elements = build_audio_capture_elements(audio)?;
+            elements.extend([
+                make_element(audio.encoder.parser_name(), "audio_parser")?,
+                build_ring_sink("audio_ring", &state, PreRollState::push_audio)?,
+            ]);
+            add_chain(&pipeline, &elements)?;
+        }
+
+        source.video.build(&pipeline, &convert)?;
+
+        pipeline.set_state(gst::State::Playing).map_err(|err| {
+            let _ = pipeline.set_state(gst::State::Null);
+            StreamError::state_change(gst::State::Playing, err)
+        })?;
+
+        Ok(Self {
+            profile,
+            pipeline,
+            state,
+        })
+    }
+
+    pub fn pipeline(&self) -> &gst::Pipeline {
+        &self.pipeline
+    }
+
+    /// Feeds the recording `id` whose `pipeline` was built with a pre-roll
+    /// input, starting with the buffered frames.
+    pub(super) fn attach(&self, id: Uuid, pipeline: &gst::Pipeline) {
+        let Some(video) = pipeline.by_name(PRE_ROLL_VIDEO_INPUT) else {
+            return;
+        };
+        let audio = pipeline.by_name(PRE_ROLL_AUDIO_INPUT);
+
+        let mut state = self.state.lock().unwrap();
+        let start = state.video.start();
+        if let Some(start) = start {
+            for (_, sample) in state.video.iter() {
+                push_frame(&video, sample, start);
+            }
+            if let Some(audio) = &audio {
+                for (_, sample) in state.audio.iter().filter(|(time, _)| *time >= start) {
+                    push_frame(audio, sample, start);
+                }
+            }
+        }
+
+        state.outputs.push(PreRollOutput {
+            id,
+            video,
+            audio,
+            start,
+        });
+    }
+
+    /// Stops feeding the recording `id`.
+    pub(super) fn detach(&self, id: Uuid) {
+        self.state
+            .lock()
+            .unwrap()
+            .outputs
+            .retain(|output| output.id != id);
+    }
+
+    /// Stops the pipeline, the recordings it feeds are ended so their files
+    /// get finalized.
+    pub(super) fn stop(&self) {
+        let outputs = std::mem::take(&mut self.state.lock().unwrap().outputs);
+        for output in outputs {
+            for input in [Some(output.video), output.audio].into_iter().flatten() {
+                let _ = input.emit_by_name::<gst::FlowReturn>("end-of-stream", &[]);
+            }
+        }
+
+        let _ = self.pipeline.set_state(gst::State::Null);
+    }
+}
+
+/// Creates the appsrc a recording pipeline reads one of the buffered streams
+/// from. Its caps are set with the first frame.
+pub(super) fn build_pre_roll_input(name: &str) -> Result<gst::Element, PipelineError> {
+    gst::ElementFactory::make("appsrc")
+        .name(name)
+        .property_from_str("format", "time")
+        .property("is-live", true)
+        // The buffered frames are pushed all at once
+        .property("max-bytes", 0u64)
+        .build()
+        .map_err(|err| PipelineError::ElementCreation("appsrc".into(), err.to_string()))
+}
+
+/// Creates an appsink handing every encoded frame to `on_sample`.
+fn build_ring_sink(
+    name: &str,
+    state: &Arc<Mutex<PreRollState>>,
+    on_sample: fn(&mut PreRollState, gst::Sample),
+) -> Result<gst::Element, PipelineError> {
+    let sink = gst::ElementFactory::make("appsink")
+        .name(name)
+        .property("emit-signals", true)
+        .property("sync", false)
+        .build()
+        .map_err(|err| PipelineError::ElementCreation("appsink".into(), err.to_string()))?;
+
+    let state = state.clone();
+    sink.connect("new-sample", false, move |args| {
+        let sample = args[0]
+            .get::<gst::Element>()
+            .ok()
+            .and_then(|sink| sink.emit_by_name::<Option<gst::Sample>>("pull-sample", &[]));
+        if let Some(sample) = sample {
+            on_sample(&mut state.lock().unwrap(), sample);
+        }
+
+        Some(gst::FlowReturn::Ok.to_value())
+    });
+
+    Ok(sink)
+}
+
+/// Timestamp of a frame and whether it is a keyframe.
+fn frame_info(sample: &gst::Sample) -> Option<(Duration, bool)> {
+    let buffer = sample.buffer()?;
+    let time = buffer.dts_or_pts()?;
+
+    Some((
+        Duration::from(time),
+        !buffer.flags().contains(gst::BufferFlags::DELTA_UNIT),
+    ))
+}
+
+/// Pushes a frame to a recording input, shifting its timestamps by `start`.
+fn push_frame(input: &gst::Element, sample: &gst::Sample, start: Duration) {
+    let Some(mut buffer) = sample.buffer_owned() else {
+        return;
+    };
+    let offset = gst::ClockTime::from_nseconds(start.as_nanos() as u64);
+    {
+        let buffer = buffer.make_mut();
+        let pts = buffer.pts().map(|pts| pts.saturating_sub(offset));
+        let dts = buffer.dts().map(|dts| dts.saturating_sub(offset));
+        buffer.set_pts(pts);
+        buffer.set_dts(dts);
+    }
+
+    if input.property::<Option<gst::Caps>>("caps").is_none() {
+        input.set_property("caps", sample.caps_owned());
+    }
+    let _ = input.emit_by_name::<gst::FlowReturn>("push-buffer", &[&buffer]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(ring: &FrameRing<u32>) -> Vec<u32> {
+        ring.iter().map(|(_, frame)| *frame).collect()
+    }
+
+    #[test]
+    fn it_should_start_with_a_keyframe() {
+        let mut ring = FrameRing::new(Duration::from_secs(2));
+        ring.push(Duration::from_secs(0), false, 0);
+        ring.push(Duration::from_secs(1), true, 1);
+        ring.push(Duration::from_secs(2), false, 2);
+
+        assert_eq!(frames(&ring), vec![1, 2]);
+        assert_eq!(ring.start(), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn it_should_keep_at_least_the_duration() {
+        let mut ring = FrameRing::new(Duration::from_secs(2));
+        for second in 0..6 {
+            ring.push(Duration::from_secs(second), second % 2 == 0, second as u32);
+        }
+
+        // Starting at 4 would only cover one second
+        assert_eq!(frames(&ring), vec![2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn it_should_trim_frames_before_a_time() {
+        let mut ring = FrameRing::new(Duration::from_secs(10));
+        for second in 0..4 {
+            ring.push(Duration::from_secs(second), true, second as u32);
+        }
+        ring.trim_before(Duration::from_secs(2));
+
+        assert_eq!(frames(&ring), vec![2, 3]);
+    }
+}
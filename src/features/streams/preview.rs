@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use axum::body::Bytes;
+use gstreamer as gst;
+use gstreamer::prelude::{ElementExt, ObjectExt, ToValue};
+use tokio::sync::broadcast;
+
+use crate::configuration::{PreviewConfiguration, SourceConfiguration};
+
+use super::pipeline::{
+    add_branches, add_chain, build_raw_branches, check_elements, make_element, PipelineError,
+};
+use super::recording_manager::RecordingManagerError;
+use super::snapshot::SNAPSHOT_TAP_ELEMENTS;
+use super::stream_error::StreamError;
+
+/// Elements of the preview branch, fed with raw video.
+pub const PREVIEW_ELEMENTS: [&str; 6] = [
+    "queue",
+    "videoscale",
+    "videorate",
+    "capsfilter",
+    "jpegenc",
+    "appsink",
+];
+
+/// Frames buffered for slow preview clients, older ones are skipped.
+const FRAME_CHANNEL_CAPACITY: usize = 4;
+
+/// Interval at which a standalone preview checks whether it is still watched.
+const WATCH_INTERVAL: gst::ClockTime = gst::ClockTime::from_mseconds(500);
+
+/// Low resolution JPEG branch publishing every encoded frame on `frames`.
+#[derive(Clone, Debug)]
+pub struct PreviewOutput {
+    pub width: u32,
+    pub height: u32,
+    pub framerate: u32,
+    pub quality: u32,
+    pub frames: broadcast::Sender<Bytes>,
+}
+
+impl PreviewOutput {
+    fn new(configuration: &PreviewConfiguration, frames: broadcast::Sender<Bytes>) -> Self {
+        Self {
+            width: configuration.width,
+            height: configuration.height,
+            framerate: configuration.framerate,
+            quality: configuration.quality,
+            frames,
+        }
+    }
+}
+
+/// Creates the preview branch:
+/// queue ! videoscale ! videorate ! capsfilter ! jpegenc ! appsink
+pub(super) fn build_preview_elements(
+    preview: &PreviewOutput,
+) -> Result<Vec<gst::Element>, PipelineError> {
+    // Never hold back the recording when the preview falls behind
+    let queue = gst::ElementFactory::make("queue")
+        .name("preview_queue")
+        .property("max-size-buffers", 1u32)
+        .property_from_str("leaky", "downstream")
+        .build()
+        .map_err(|err| PipelineError::ElementCreation("queue".into(), err.to_string()))?;
+    let caps = gst::Caps::builder("video/x-raw")
+        .field("width", preview.width as i32)
+        .field("height", preview.height as i32)
+        .field("framerate", gst::Fraction::new(preview.framerate as i32, 1))
+        .build();
+    let capsfilter = gst::ElementFactory::make("capsfilter")
+        .name("preview_caps")
+        .property("caps", caps)
+        .build()
+        .map_err(|err| PipelineError::ElementCreation("capsfilter".into(), err.to_string()))?;
+    let encoder = gst::ElementFactory::make("jpegenc")
+        .name("preview_encoder")
+        .property("quality", preview.quality as i32)
+        .build()
+        .map_err(|err| PipelineError::ElementCreation("jpegenc".into(), err.to_string()))?;
+    let sink = gst::ElementFactory::make("appsink")
+        .name("preview_sink")
+        .property("emit-signals", true)
+        .property("max-buffers", 1u32)
+        .property("drop", true)
+        .property("sync", false)
+        .build()
+        .map_err(|err| PipelineError::ElementCreation("appsink".into(), err.to_string()))?;
+
+    let frames = preview.frames.clone();
+    sink.connect("new-sample", false, move |args| {
+        let sink = args[0].get::<gst::Element>().ok()?;
+        let sample = sink.emit_by_name::<Option<gst::Sample>>("pull-sample", &[]);
+        if let Some(buffer) = sample.as_ref().and_then(|sample| sample.buffer()) {
+            if let Ok(map) = buffer.map_readable() {
+                // Nobody may be watching, the frame is then dropped
+                let _ = frames.send(Bytes::copy_from_slice(map.as_slice()));
+            }
+        }
+
+        Some(gst::FlowReturn::Ok.to_value())
+    });
+
+    Ok(vec![
+        queue,
+        make_element("videoscale", "preview_scale")?,
+        make_element("videorate", "preview_rate")?,
+        capsfilter,
+        encoder,
+        sink,
+    ])
+}
+
+/// Pipeline previewing a source that is not being recorded, with a snapshot
+/// tap since it holds the capture device:
+/// source ! videoconvert ! tee ! <preview branch>
+///                         tee ! <snapshot tap>
+fn build_preview_pipeline(
+    source: &SourceConfiguration,
+    preview: &PreviewOutput,
+) -> Result<gst::Pipeline, PipelineError> {
+    let mut elements = source.video.element_names();
+    elements.extend(["videoconvert", "tee"]);
+    elements.extend(PREVIEW_ELEMENTS);
+    elements.extend(SNAPSHOT_TAP_ELEMENTS);
+    check_elements(&elements)?;
+
+    let pipeline = gst::Pipeline::new();
+    let convert = make_element("videoconvert", "convert")?;
+    let raw_tee = make_element("tee", "raw_tee")?;
+    add_chain(&pipeline, &[convert.clone(), raw_tee.clone()])?;
+    add_branches(
+        &pipeline,
+        &build_raw_branches(&raw_tee, Some(preview), true)?,
+    )?;
+    source.video.build(&pipeline, &convert)?;
+
+    Ok(pipeline)
+}
+
+struct PreviewChannel {
+    frames: broadcast::Sender<Bytes>,
+    /// Pipeline feeding the channel while the source is not recorded.
+    standalone: Option<gst::Pipeline>,
+    /// Set while a recording of the source feeds the channel.
+    live: bool,
+}
+
+impl PreviewChannel {
+    fn new() -> Self {
+        let (frames, _) = broadcast::channel(FRAME_CHANNEL_CAPACITY);
+
+        Self {
+            frames,
+            standalone: None,
+            live: false,
+        }
+    }
+}
+
+/// Preview frames of every source. The recording holding the live outputs of
+/// a source feeds them, a standalone preview pipeline does otherwise. The
+/// standalone pipeline only runs while clients are watching.
+#[derive(Default)]
+pub struct PreviewHub {
+    channels: Mutex<HashMap<String, PreviewChannel>>,
+}
+
+impl PreviewHub {
+    pub fn subscribe(
+        self: &Arc<Self>,
+        source: &SourceConfiguration,
+        configuration: &PreviewConfiguration,
+    ) -> Result<broadcast::Receiver<Bytes>, RecordingManagerError> {
+        let mut channels = self.channels.lock().unwrap();
+        let channel = channels
+            .entry(source.name.clone())
+            .or_insert_with(PreviewChannel::new);
+
+        if !channel.live && channel.standalone.is_none() {
+            let output = PreviewOutput::new(configuration, channel.frames.clone());
+            channel.standalone = Some(self.start_standalone(source, &output)?);
+        }
+
+        Ok(channel.frames.subscribe())
+    }
+
+    /// Standalone preview pipeline of `source`, when one is running.
+    pub fn standalone_pipeline(&self, source: &str) -> Option<gst::Pipeline> {
+        self.channels
+            .lock()
+            .unwrap()
+            .get(source)
+            .and_then(|channel| channel.standalone.clone())
+    }
+
+    /// Hands the preview of `source` over to a starting recording. The
+    /// standalone pipeline is stopped first to release the capture device.
+    pub fn take_over(&self, source: &str, configuration: &PreviewConfiguration) -> PreviewOutput {
+        let mut channels = self.channels.lock().unwrap();
+        let channel = channels
+            .entry(source.to_owned())
+            .or_insert_with(PreviewChannel::new);
+
+        channel.live = true;
+        if let Some(pipeline) = channel.standalone.take() {
+            let _ = pipeline.set_state(gst::State::Null);
+        }
+
+        PreviewOutput::new(configuration, channel.frames.clone())
+    }
+
+    /// Called once the recording feeding the preview of `source` ended,
+    /// resumes the standalone pipeline for the clients still watching.
+    pub fn release(
+        self: &Arc<Self>,
+        source: &SourceConfiguration,
+        configuration: &PreviewConfiguration,
+    ) {
+        let mut channels = self.channels.lock().unwrap();
+        let Some(channel) = channels.get_mut(&source.name) else {
+            return;
+        };
+
+        channel.live = false;
+        if channel.frames.receiver_count() == 0 || channel.standalone.is_some() {
+            return;
+        }
+
+        let output = PreviewOutput::new(configuration, channel.frames.clone());
+        match self.start_standalone(source, &output) {
+            Ok(pipeline) => channel.standalone = Some(pipeline),
+            Err(err) => {
+                tracing::warn!("Failed to resume preview of {}: {}", source.name, err);
+                // Ends the streams of the remaining clients
+                channels.remove(&source.name);
+            }
+        }
+    }
+
+    fn start_standalone(
+        self: &Arc<Self>,
+        source: &SourceConfiguration,
+        output: &PreviewOutput,
+    ) -> Result<gst::Pipeline, RecordingManagerError> {
+        let pipeline = build_preview_pipeline(source, output)?;
+        let bus = pipeline.bus().ok_or_else(|| StreamError::Other {
+            message: String::from("Pipeline has no bus"),
+        })?;
+
+        pipeline.set_state(gst::State::Playing).map_err(|err| {
+            let _ = pipeline.set_state(gst::State::Null);
+            StreamError::state_change(gst::State::Playing, err)
+        })?;
+
+        let hub = self.clone();
+        let source = source.name.clone();
+        let watched_pipeline = pipeline.clone();
+        std::thread::spawn(move || hub.watch_standalone(source, watched_pipeline, bus));
+
+        Ok(pipeline)
+    }
+
+    /// Stops a standalone preview once its last client left or when it fails.
+    /// A recording taking the preview over stops the pipeline itself.
+    fn watch_standalone(&self, source: String, pipeline: gst::Pipeline, bus: gst::Bus) {
+        let failed = loop {
+            if let Some(msg) = bus.timed_pop(WATCH_INTERVAL) {
+                match msg.view() {
+                    gst::MessageView::Error(err) => {
+                        tracing::warn!(
+                            "Preview of {} failed: {}",
+                            source,
+                            StreamError::from_error_message(err)
+                        );
+                        break true;
+                    }
+                    gst::MessageView::Eos(..) => break true,
+                    _ => (),
+                }
+            }
+
+            let channels = self.channels.lock().unwrap();
+            match channels.get(&source) {
+                Some(channel) if channel.standalone.as_ref() == Some(&pipeline) => {
+                    if channel.frames.receiver_count() == 0 {
+                        break false;
+                    }
+                }
+                _ => return,
+            }
+        };
+
+        let mut channels = self.channels.lock().unwrap();
+        let is_current = channels
+            .get(&source)
+            .is_some_and(|channel| channel.standalone.as_ref() == Some(&pipeline));
+        if is_current && failed {
+            // Ends the streams of the clients
+            channels.remove(&source);
+        } else if let Some(channel) = channels.get_mut(&source).filter(|_| is_current) {
+            channel.standalone = None;
+        }
+        let _ = pipeline.set_state(gst::State::Null);
+    }
+}
@@ -0,0 +1,1169 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{mpsc, Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use axum::body::Bytes;
+use chrono::{DateTime, Utc};
+use gstreamer as gst;
+use gstreamer::prelude::{ElementExt, ElementExtManual, GstObjectExt};
+use schemars::JsonSchema;
+use serde::Serialize;
+use thiserror::Error;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::configuration::{
+    MediaConfiguration, MotionConfiguration, PreRollConfiguration, PreviewConfiguration,
+    SourceConfiguration, ThumbnailConfiguration,
+};
+use crate::service::Service;
+
+use super::motion::{MotionEvent, MotionOutput, MotionTransition};
+use super::output_path::{
+    allocate_output_path, resolve_output_path, sanitize_component, TemplateContext,
+};
+use super::pipeline::{
+    build_pipeline, EncodingProfile, HlsOutput, PipelineError, PipelineSpec, SegmentPolicy,
+};
+use super::pre_roll::PreRollBuffer;
+use super::preview::PreviewHub;
+use super::snapshot::{capture_frame, encode_frame, last_frame, SnapshotFormat};
+use super::stream_error::StreamError;
+use super::thumbnails::{generate_previews, RecordingPreviews};
+
+/// Interval at which the bus watcher wakes up to check the recording deadline.
+const BUS_POLL_INTERVAL: gst::ClockTime = gst::ClockTime::from_mseconds(100);
+
+/// Number of recording updates buffered for slow event subscribers.
+const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// Number of motion events buffered for slow subscribers.
+const MOTION_CHANNEL_CAPACITY: usize = 64;
+
+/// How long finished recordings stay in memory, the database keeps them after.
+const FINISHED_SESSION_RETENTION: chrono::Duration = chrono::Duration::hours(1);
+
+#[derive(Debug, Error)]
+pub enum RecordingManagerError {
+    #[error("Media capture is disabled")]
+    MediaDisabled,
+    #[error("Recording with id {0} not found")]
+    RecordingNotFound(Uuid),
+    #[error("Source {0} is not configured")]
+    SourceNotFound(String),
+    #[error("Encoding profile {0} is not configured")]
+    ProfileNotFound(String),
+    #[error("Recording with id {0} is not active")]
+    RecordingNotActive(Uuid),
+    #[error("Recording duration of {0} seconds is out of range")]
+    InvalidDuration(u64),
+    #[error("Preview is disabled")]
+    PreviewDisabled,
+    #[error("Source {0} is mounted on the RTSP server, unmount it first")]
+    SourceMounted(String),
+    #[error("Invalid pipeline: {0}")]
+    InvalidPipeline(#[from] PipelineError),
+    #[error(transparent)]
+    StreamError(#[from] StreamError),
+    #[error("I/O error: {0}")]
+    IoError(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordingState {
+    Pending,
+    Recording,
+    Finalizing,
+    Completed,
+    Failed,
+}
+
+impl RecordingState {
+    pub fn is_active(&self) -> bool {
+        matches!(self, RecordingState::Pending | RecordingState::Recording)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RecordingState::Pending => "pending",
+            RecordingState::Recording => "recording",
+            RecordingState::Finalizing => "finalizing",
+            RecordingState::Completed => "completed",
+            RecordingState::Failed => "failed",
+        }
+    }
+}
+
+impl FromStr for RecordingState {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "pending" => Ok(RecordingState::Pending),
+            "recording" => Ok(RecordingState::Recording),
+            "finalizing" => Ok(RecordingState::Finalizing),
+            "completed" => Ok(RecordingState::Completed),
+            "failed" => Ok(RecordingState::Failed),
+            _ => Err(format!("Unknown recording state: {}", value)),
+        }
+    }
+}
+
+/// Parameters of a recording request.
+#[derive(Clone, Debug, Default)]
+pub struct RecordingRequest {
+    pub owner_id: Option<Uuid>,
+    /// Name of the configured source, the first source when unset.
+    pub source: Option<String>,
+    /// Overrides `media.recording_duration` when set.
+    pub duration: Option<Duration>,
+    /// Splits the recording in segments, each one registered as a recording
+    /// whose `parent_id` is the id of this recording.
+    pub segments: Option<SegmentPolicy>,
+    /// Name of the encoding profile, `media.default_profile` when unset.
+    pub profile: Option<String>,
+}
+
+/// Snapshot of a recording session, safe to hand out to callers.
+#[derive(Clone, Debug)]
+pub struct RecordingInfo {
+    pub id: Uuid,
+    pub parent_id: Option<Uuid>,
+    pub owner_id: Option<Uuid>,
+    pub source: String,
+    pub codec: String,
+    pub container: String,
+    pub state: RecordingState,
+    pub location: PathBuf,
+    pub duration: Option<Duration>,
+    pub size: Option<u64>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub error: Option<StreamError>,
+    /// Written in the background once the recording completed.
+    pub previews: RecordingPreviews,
+}
+
+struct RecordingSession {
+    info: RecordingInfo,
+    /// Segments share the pipeline of their parent and have none.
+    pipeline: Option<gst::Pipeline>,
+}
+
+/// Sessions shared between the manager and the bus watcher threads. Every
+/// change to a session is published on `events`.
+struct Registry {
+    sessions: Mutex<HashMap<Uuid, RecordingSession>>,
+    events: broadcast::Sender<RecordingInfo>,
+    motion_events: broadcast::Sender<MotionEvent>,
+    /// Recording feeding the live outputs of each source.
+    live_sources: Mutex<HashMap<String, Uuid>>,
+    /// Pre-roll buffers by source, they feed the preview, snapshot tap and
+    /// motion detection of their source instead of its recordings.
+    pre_rolls: Mutex<HashMap<String, Arc<PreRollBuffer>>>,
+    previews: Arc<PreviewHub>,
+    /// Sources mounted on the RTSP server, whose capture device the mount
+    /// holds.
+    rtsp_mounts: Mutex<HashSet<String>>,
+    thumbnails: Option<ThumbnailConfiguration>,
+}
+
+impl Registry {
+    fn insert(&self, session: RecordingSession) {
+        let info = session.info.clone();
+        let mut sessions = self.sessions.lock().unwrap();
+
+        let expired_before = Utc::now() - FINISHED_SESSION_RETENTION;
+        sessions.retain(|_, session| {
+            session
+                .info
+                .finished_at
+                .map_or(true, |finished_at| finished_at > expired_before)
+        });
+
+        sessions.insert(info.id, session);
+        let _ = self.events.send(info);
+    }
+
+    fn get(&self, id: Uuid) -> Option<RecordingInfo> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(&id)
+            .map(|session| session.info.clone())
+    }
+
+    /// Locks the RTSP mounts, failing when one holds the capture device of
+    /// `source`. Holding the guard until the capture is registered keeps
+    /// `RecordingManager::claim_rtsp` from mounting the source meanwhile.
+    fn lock_unmounted(
+        &self,
+        source: &str,
+    ) -> Result<MutexGuard<'_, HashSet<String>>, RecordingManagerError> {
+        let rtsp_mounts = self.rtsp_mounts.lock().unwrap();
+        match rtsp_mounts.contains(source) {
+            true => Err(RecordingManagerError::SourceMounted(source.to_owned())),
+            false => Ok(rtsp_mounts),
+        }
+    }
+
+    /// Reserves the live outputs of `source` for the recording `id`, `None`
+    /// when another recording already feeds them.
+    fn claim_live_source(
+        self: &Arc<Self>,
+        id: Uuid,
+        source: &SourceConfiguration,
+        preview: Option<PreviewConfiguration>,
+    ) -> Option<LiveSourceClaim> {
+        let mut live_sources = self.live_sources.lock().unwrap();
+        if live_sources.contains_key(&source.name) {
+            return None;
+        }
+        live_sources.insert(source.name.clone(), id);
+
+        Some(LiveSourceClaim {
+            registry: self.clone(),
+            source: source.clone(),
+            preview,
+        })
+    }
+
+    fn pre_roll(&self, source: &str) -> Option<Arc<PreRollBuffer>> {
+        self.pre_rolls.lock().unwrap().get(source).cloned()
+    }
+
+    /// Stops the pre-roll buffer of `source` once its pipeline failed, the
+    /// recordings it feeds end and the next ones capture the source again.
+    fn watch_pre_roll(
+        &self,
+        buffer: Arc<PreRollBuffer>,
+        source: SourceConfiguration,
+        preview: Option<PreviewConfiguration>,
+        bus: gst::Bus,
+    ) {
+        let msg = bus.timed_pop_filtered(
+            gst::ClockTime::NONE,
+            &[gst::MessageType::Error, gst::MessageType::Eos],
+        );
+        let error = match msg.as_ref().map(|msg| msg.view()) {
+            Some(gst::MessageView::Error(err)) => StreamError::from_error_message(err),
+            _ => StreamError::Other {
+                message: String::from("Pipeline ended"),
+            },
+        };
+        tracing::error!("Pre-roll buffer of {} stopped: {}", source.name, error);
+
+        self.pre_rolls.lock().unwrap().remove(&source.name);
+        buffer.stop();
+        if let Some(preview) = &preview {
+            self.previews.release(&source, preview);
+        }
+    }
+
+    fn update(&self, id: Uuid, update: impl FnOnce(&mut RecordingInfo)) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(session) = sessions.get_mut(&id) {
+            update(&mut session.info);
+            let _ = self.events.send(session.info.clone());
+        }
+    }
+
+    /// Extracts the poster frame and thumbnails of a completed recording on
+    /// a background thread, publishing them once written.
+    fn generate_previews(self: &Arc<Self>, id: Uuid) {
+        let (Some(configuration), Some(info)) = (self.thumbnails.clone(), self.get(id)) else {
+            return;
+        };
+
+        let registry = self.clone();
+        std::thread::spawn(
+            move || match generate_previews(&info.location, &configuration) {
+                Ok(previews) => registry.update(id, |info| info.previews = previews),
+                Err(err) => {
+                    tracing::warn!("Failed to generate previews of recording {}: {}", id, err)
+                }
+            },
+        );
+    }
+}
+
+/// Live outputs of a source, HLS, preview and snapshot tap, held by a
+/// recording. Dropping the claim hands the preview back to a standalone
+/// pipeline.
+struct LiveSourceClaim {
+    registry: Arc<Registry>,
+    source: SourceConfiguration,
+    preview: Option<PreviewConfiguration>,
+}
+
+impl Drop for LiveSourceClaim {
+    fn drop(&mut self) {
+        if let Some(preview) = &self.preview {
+            self.registry.previews.release(&self.source, preview);
+        }
+        self.registry
+            .live_sources
+            .lock()
+            .unwrap()
+            .remove(&self.source.name);
+    }
+}
+
+/// Long-lived service owning every recording pipeline of the process.
+#[derive(Clone)]
+pub struct RecordingManager {
+    pub name: String,
+    configuration: MediaConfiguration,
+    registry: Arc<Registry>,
+}
+
+impl Service for RecordingManager {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl RecordingManager {
+    pub fn new(configuration: MediaConfiguration) -> Self {
+        let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        let (motion_events, _) = broadcast::channel(MOTION_CHANNEL_CAPACITY);
+
+        Self {
+            name: String::from("RecordingManager"),
+            registry: Arc::new(Registry {
+                sessions: Mutex::new(HashMap::new()),
+                events,
+                motion_events,
+                live_sources: Mutex::new(HashMap::new()),
+                pre_rolls: Mutex::new(HashMap::new()),
+                previews: Arc::new(PreviewHub::default()),
+                rtsp_mounts: Mutex::new(HashSet::new()),
+                thumbnails: configuration.thumbnails.clone(),
+            }),
+            configuration,
+        }
+    }
+
+    /// Subscribes to recording updates, each message is the latest snapshot
+    /// of a recording whose state changed.
+    pub fn subscribe(&self) -> broadcast::Receiver<RecordingInfo> {
+        self.registry.events.subscribe()
+    }
+
+    /// Subscribes to the motion events of every source.
+    pub fn subscribe_motion(&self) -> broadcast::Receiver<MotionEvent> {
+        self.registry.motion_events.subscribe()
+    }
+
+    /// Starts the pre-roll buffers configured on the sources, returning the
+    /// name of each started source. Sources watched for motion get one as
+    /// well, since the detection runs on the buffer pipeline. They run until
+    /// the process exits.
+    pub fn start_pre_roll_buffers(&self) -> Vec<Result<String, RecordingManagerError>> {
+        if !self.configuration.enabled {
+            return Vec::new();
+        }
+
+        self.configuration
+            .sources
+            .iter()
+            .filter(|source| source.pre_roll.is_some() || source.motion.is_some())
+            .map(|source| {
+                self.start_pre_roll(source, source.pre_roll.as_ref(), source.motion.as_ref())
+                    .map(|_| source.name.clone())
+            })
+            .collect()
+    }
+
+    fn start_pre_roll(
+        &self,
+        source: &SourceConfiguration,
+        pre_roll: Option<&PreRollConfiguration>,
+        motion: Option<&MotionConfiguration>,
+    ) -> Result<(), RecordingManagerError> {
+        let _rtsp_mounts = self.registry.lock_unmounted(&source.name)?;
+        let profile_name = pre_roll
+            .and_then(|pre_roll| pre_roll.profile.as_deref())
+            .or(self.configuration.default_profile.as_deref());
+        let profile = self
+            .configuration
+            .find_profile(profile_name)
+            .ok_or_else(|| {
+                RecordingManagerError::ProfileNotFound(profile_name.unwrap_or_default().to_owned())
+            })?;
+
+        gst::init().map_err(|err| StreamError::Initialization {
+            message: err.to_string(),
+        })?;
+
+        let preview_configuration = self.configuration.preview.clone();
+        let preview = preview_configuration
+            .as_ref()
+            .map(|preview| self.registry.previews.take_over(&source.name, preview));
+        let (transitions, motion) = match motion {
+            Some(motion) => {
+                let (sender, receiver) = mpsc::channel();
+                let output = MotionOutput {
+                    configuration: motion.clone(),
+                    transitions: sender,
+                };
+                (Some(receiver), Some(output))
+            }
+            None => (None, None),
+        };
+        let buffer = PreRollBuffer::start(
+            source,
+            Duration::from_secs(pre_roll.map_or(0, |pre_roll| pre_roll.duration)),
+            profile,
+            preview.as_ref(),
+            motion.as_ref(),
+        )
+        .inspect_err(|_| {
+            if let Some(preview) = &preview_configuration {
+                self.registry.previews.release(source, preview);
+            }
+        })?;
+        let bus = buffer.pipeline().bus().ok_or_else(|| StreamError::Other {
+            message: String::from("Pipeline has no bus"),
+        })?;
+
+        let buffer = Arc::new(buffer);
+        self.registry
+            .pre_rolls
+            .lock()
+            .unwrap()
+            .insert(source.name.clone(), buffer.clone());
+
+        if let (Some(transitions), Some(motion)) = (transitions, motion) {
+            let manager = self.clone();
+            let source = source.name.clone();
+            std::thread::spawn(move || {
+                manager.watch_motion(source, motion.configuration.record, transitions)
+            });
+        }
+
+        let registry = self.registry.clone();
+        let source = source.clone();
+        std::thread::spawn(move || {
+            registry.watch_pre_roll(buffer, source, preview_configuration, bus)
+        });
+
+        Ok(())
+    }
+
+    /// Publishes the motion of `source`, recording it while motion lasts
+    /// when `record` is set. Returns once the buffer pipeline is released.
+    fn watch_motion(
+        &self,
+        source: String,
+        record: bool,
+        transitions: mpsc::Receiver<MotionTransition>,
+    ) {
+        let mut recording_id = None;
+        for transition in transitions {
+            let started = matches!(transition, MotionTransition::Started { .. });
+            if started && record && recording_id.is_none() {
+                let request = RecordingRequest {
+                    source: Some(source.clone()),
+                    duration: Some(Duration::ZERO),
+                    ..Default::default()
+                };
+                match self.start(request) {
+                    Ok(info) => recording_id = Some(info.id),
+                    Err(err) => tracing::error!("Failed to record motion of {}: {}", source, err),
+                }
+            }
+
+            tracing::info!("Motion {:?} on source {}", transition, source);
+            let _ = self.registry.motion_events.send(MotionEvent {
+                source: source.clone(),
+                transition,
+                at: Utc::now(),
+                recording_id,
+            });
+
+            if !started {
+                if let Some(id) = recording_id.take() {
+                    // The recording may already have ended with its buffer
+                    let _ = self.stop(id);
+                }
+            }
+        }
+
+        if let Some(id) = recording_id {
+            let _ = self.stop(id);
+        }
+    }
+
+    /// Starts the continuous recordings configured on the sources. They run
+    /// until stopped, rotating segments as configured.
+    pub fn start_continuous_recordings(&self) -> Vec<Result<RecordingInfo, RecordingManagerError>> {
+        if !self.configuration.enabled {
+            return Vec::new();
+        }
+
+        self.configuration
+            .sources
+            .iter()
+            .filter_map(|source| {
+                let continuous = source.continuous.as_ref()?;
+
+                Some(
+                    self.start(RecordingRequest {
+                        owner_id: None,
+                        source: Some(source.name.clone()),
+                        duration: Some(Duration::ZERO),
+                        segments: Some(SegmentPolicy {
+                            max_duration: Some(Duration::from_secs(continuous.segment_duration)),
+                            max_size_bytes: continuous
+                                .segment_size
+                                .map(|size| size.saturating_mul(1024 * 1024)),
+                        }),
+                        profile: continuous.profile.clone(),
+                    }),
+                )
+            })
+            .collect()
+    }
+
+    /// Starts a new recording. When no duration is requested the configured
+    /// `recording_duration` is used, a zero duration records until stopped.
+    pub fn start(&self, request: RecordingRequest) -> Result<RecordingInfo, RecordingManagerError> {
+        if !self.configuration.enabled {
+            return Err(RecordingManagerError::MediaDisabled);
+        }
+
+        let source = self
+            .configuration
+            .find_source(request.source.as_deref())
+            .ok_or_else(|| {
+                RecordingManagerError::SourceNotFound(request.source.clone().unwrap_or_default())
+            })?;
+        let _rtsp_mounts = self.registry.lock_unmounted(&source.name)?;
+        // A pre-roll buffer already encodes the source with its own profile
+        let pre_roll = self.registry.pre_roll(&source.name);
+        let profile = match &pre_roll {
+            Some(pre_roll) => {
+                if let Some(profile) = &request.profile {
+                    tracing::warn!(
+                        "Source {} is recorded with its pre-roll profile, {} is ignored",
+                        source.name,
+                        profile
+                    );
+                }
+                pre_roll.profile.clone()
+            }
+            None => {
+                let profile_name = request
+                    .profile
+                    .as_deref()
+                    .or(self.configuration.default_profile.as_deref());
+                self.configuration
+                    .find_profile(profile_name)
+                    .ok_or_else(|| {
+                        RecordingManagerError::ProfileNotFound(
+                            profile_name.unwrap_or_default().to_owned(),
+                        )
+                    })?
+            }
+        };
+
+        let max_duration = request
+            .duration
+            .unwrap_or_else(|| Duration::from_secs(self.configuration.recording_duration.into()));
+        let max_duration = (!max_duration.is_zero()).then_some(max_duration);
+        // The deadline of the recording must be representable
+        if let Some(max_duration) = max_duration {
+            if Instant::now().checked_add(max_duration).is_none() {
+                return Err(RecordingManagerError::InvalidDuration(
+                    max_duration.as_secs(),
+                ));
+            }
+        }
+
+        gst::init().map_err(|err| StreamError::Initialization {
+            message: err.to_string(),
+        })?;
+
+        let id = Uuid::new_v4();
+        let started_at = Utc::now();
+        let container = self.configuration.container;
+        let context = TemplateContext {
+            id,
+            source: &source.name,
+            extension: container.extension(),
+            timestamp: started_at,
+        };
+        // Segmented recordings reserve one file per segment instead
+        let location = match request.segments {
+            Some(_) => resolve_output_path(
+                &self.configuration.output_folder,
+                &self.configuration.file_name_template,
+                &context,
+            ),
+            None => allocate_output_path(
+                &self.configuration.output_folder,
+                &self.configuration.file_name_template,
+                &context,
+            ),
+        }
+        .map_err(|err| RecordingManagerError::IoError(err.to_string()))?;
+        // Releases the file name reserved for this recording when it fails
+        // to start
+        let release_location = |location: &Path| {
+            if request.segments.is_none() {
+                let _ = std::fs::remove_file(location);
+            }
+        };
+
+        // The first recording of a source also feeds its live outputs, but
+        // for the preview and snapshot tap held by a pre-roll buffer
+        let live_preview = match &pre_roll {
+            Some(_) => None,
+            None => self.configuration.preview.clone(),
+        };
+        let live_claim = self
+            .registry
+            .claim_live_source(id, source, live_preview.clone());
+        // MPEG-TS segments only carry H.264 and H.265
+        let hls = match (&live_claim, &self.configuration.hls) {
+            (Some(_), Some(_)) if profile.encoder.supports_hls() => Some(
+                self.prepare_hls_output(&source.name)
+                    .inspect_err(|_| release_location(&location))?,
+            ),
+            _ => None,
+        };
+        let preview = match (&live_claim, &live_preview) {
+            (Some(_), Some(preview)) => {
+                Some(self.registry.previews.take_over(&source.name, preview))
+            }
+            _ => None,
+        };
+
+        let spec = PipelineSpec {
+            source: source.video.clone(),
+            audio: source.audio.clone(),
+            profile,
+            container,
+            location,
+            segments: request.segments,
+            hls,
+            preview,
+            snapshot_tap: live_claim.is_some() && pre_roll.is_none(),
+            pre_roll: pre_roll.is_some(),
+        };
+
+        let pipeline = build_pipeline(&spec).inspect_err(|_| release_location(&spec.location))?;
+        let bus = pipeline
+            .bus()
+            .ok_or_else(|| StreamError::Other {
+                message: String::from("Pipeline has no bus"),
+            })
+            .inspect_err(|_| release_location(&spec.location))?;
+
+        let info = RecordingInfo {
+            id,
+            parent_id: None,
+            owner_id: request.owner_id,
+            source: source.name.clone(),
+            // Codecs of every recorded stream, such as `h264,opus`
+            codec: match &spec.audio {
+                Some(audio) => {
+                    format!("{},{}", spec.profile.encoder.codec(), audio.encoder.codec())
+                }
+                None => spec.profile.encoder.codec().to_owned(),
+            },
+            container: spec.container.name().to_owned(),
+            state: RecordingState::Pending,
+            location: spec.location,
+            duration: None,
+            size: None,
+            started_at,
+            finished_at: None,
+            error: None,
+            previews: RecordingPreviews::default(),
+        };
+
+        self.registry.insert(RecordingSession {
+            info: info.clone(),
+            pipeline: Some(pipeline.clone()),
+        });
+
+        if let Err(err) = pipeline.set_state(gst::State::Playing) {
+            // The bus usually holds the element error explaining the refusal
+            let error = bus
+                .pop_filtered(&[gst::MessageType::Error])
+                .and_then(|msg| match msg.view() {
+                    gst::MessageView::Error(err) => Some(StreamError::from_error_message(err)),
+                    _ => None,
+                })
+                .unwrap_or_else(|| StreamError::state_change(gst::State::Playing, err));
+            let _ = pipeline.set_state(gst::State::Null);
+            release_location(&info.location);
+
+            tracing::error!("Recording {} failed to start: {}", id, error);
+            self.registry.update(id, |info| {
+                info.state = RecordingState::Failed;
+                info.finished_at = Some(Utc::now());
+                info.error = Some(error.clone());
+            });
+
+            return Err(error.into());
+        }
+
+        if let Some(pre_roll) = &pre_roll {
+            pre_roll.attach(id, &pipeline);
+        }
+
+        let registry = self.registry.clone();
+        std::thread::spawn(move || {
+            watch_bus(registry, id, pipeline, bus, max_duration);
+            if let Some(pre_roll) = pre_roll {
+                pre_roll.detach(id);
+            }
+            drop(live_claim);
+        });
+
+        Ok(info)
+    }
+
+    /// Subscribes to the MJPEG preview frames of `source`, starting a preview
+    /// pipeline when the source is not being recorded.
+    pub fn subscribe_preview(
+        &self,
+        source: &str,
+    ) -> Result<broadcast::Receiver<Bytes>, RecordingManagerError> {
+        if !self.configuration.enabled {
+            return Err(RecordingManagerError::MediaDisabled);
+        }
+        let preview = self
+            .configuration
+            .preview
+            .as_ref()
+            .ok_or(RecordingManagerError::PreviewDisabled)?;
+        let source = self
+            .configuration
+            .find_source(Some(source))
+            .ok_or_else(|| RecordingManagerError::SourceNotFound(source.to_owned()))?;
+        let _rtsp_mounts = self.registry.lock_unmounted(&source.name)?;
+
+        gst::init().map_err(|err| StreamError::Initialization {
+            message: err.to_string(),
+        })?;
+
+        self.registry.previews.subscribe(source, preview)
+    }
+
+    /// Takes a still frame of `source`. The frame comes from the running
+    /// pipeline of the source when there is one, from a short-lived pipeline
+    /// otherwise. Blocks until the frame is encoded.
+    pub fn snapshot(
+        &self,
+        source: &str,
+        format: SnapshotFormat,
+    ) -> Result<Bytes, RecordingManagerError> {
+        if !self.configuration.enabled {
+            return Err(RecordingManagerError::MediaDisabled);
+        }
+        let source = self
+            .configuration
+            .find_source(Some(source))
+            .ok_or_else(|| RecordingManagerError::SourceNotFound(source.to_owned()))?;
+
+        gst::init().map_err(|err| StreamError::Initialization {
+            message: err.to_string(),
+        })?;
+
+        let live_pipeline = self.live_pipeline(&source.name);
+        match live_pipeline {
+            Some(pipeline) => {
+                let frame = last_frame(&pipeline).ok_or_else(|| StreamError::Other {
+                    message: format!("No frame received from {} yet", source.name),
+                })?;
+                encode_frame(&frame, format)
+            }
+            None => {
+                let _rtsp_mounts = self.registry.lock_unmounted(&source.name)?;
+                capture_frame(source, format)
+            }
+        }
+    }
+
+    /// Reserves the capture device of `source` for its RTSP mount, which runs
+    /// a capture pipeline of its own. Returns false while the source is
+    /// captured already. Recordings, previews and snapshots needing the
+    /// device fail until `release_rtsp` is called. They hold the mounts lock
+    /// until their capture is registered, so none is missed here.
+    pub fn claim_rtsp(&self, source: &str) -> bool {
+        let mut rtsp_mounts = self.registry.rtsp_mounts.lock().unwrap();
+        let live = self.registry.pre_roll(source).is_some()
+            || self
+                .registry
+                .live_sources
+                .lock()
+                .unwrap()
+                .contains_key(source);
+        let recorded = self
+            .registry
+            .sessions
+            .lock()
+            .unwrap()
+            .values()
+            .any(|session| session.info.source == source && session.info.state.is_active());
+        if live || recorded || self.live_pipeline(source).is_some() {
+            return false;
+        }
+
+        rtsp_mounts.insert(source.to_owned())
+    }
+
+    /// Hands the capture device of `source` back once its RTSP mount is
+    /// removed.
+    pub fn release_rtsp(&self, source: &str) {
+        self.registry.rtsp_mounts.lock().unwrap().remove(source);
+    }
+
+    /// Pipeline holding the capture device of `source`, either its pre-roll
+    /// buffer, the recording feeding its live outputs or its standalone
+    /// preview.
+    fn live_pipeline(&self, source: &str) -> Option<gst::Pipeline> {
+        if let Some(pre_roll) = self.registry.pre_roll(source) {
+            return Some(pre_roll.pipeline().clone());
+        }
+
+        let live_id = self
+            .registry
+            .live_sources
+            .lock()
+            .unwrap()
+            .get(source)
+            .copied();
+        let recording_pipeline = live_id.and_then(|id| {
+            self.registry
+                .sessions
+                .lock()
+                .unwrap()
+                .get(&id)
+                .and_then(|session| session.pipeline.clone())
+        });
+
+        recording_pipeline.or_else(|| self.registry.previews.standalone_pipeline(source))
+    }
+
+    /// Whether a running recording feeds the live outputs of `source`.
+    pub fn is_live(&self, source: &str) -> bool {
+        self.registry
+            .live_sources
+            .lock()
+            .unwrap()
+            .contains_key(source)
+    }
+
+    /// Configured encoding profiles by name, with whether their encoder is
+    /// installed. Profiles such as `v4l2h264enc` ones only work on some hosts.
+    pub fn list_profiles(&self) -> Vec<(String, EncodingProfile, bool)> {
+        let initialized = gst::init().is_ok();
+        let mut profiles: Vec<(String, EncodingProfile, bool)> = self
+            .configuration
+            .profiles
+            .iter()
+            .map(|(name, profile)| {
+                let available = initialized
+                    && gst::ElementFactory::find(profile.encoder.element_name()).is_some();
+                (name.clone(), profile.clone(), available)
+            })
+            .collect();
+        profiles.sort_by(|a, b| a.0.cmp(&b.0));
+
+        profiles
+    }
+
+    /// Directory of the HLS playlist and segments of `source`.
+    pub fn hls_directory(&self, source: &str) -> Option<PathBuf> {
+        let hls = self.configuration.hls.as_ref()?;
+
+        Some(hls.folder.join(sanitize_component(source)))
+    }
+
+    /// Empties the HLS directory of `source` so the playlist of a previous
+    /// recording is never served.
+    fn prepare_hls_output(&self, source: &str) -> Result<HlsOutput, RecordingManagerError> {
+        let (Some(hls), Some(directory)) = (&self.configuration.hls, self.hls_directory(source))
+        else {
+            return Err(RecordingManagerError::IoError(String::from(
+                "HLS output is not configured",
+            )));
+        };
+
+        match std::fs::remove_dir_all(&directory) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                return Err(RecordingManagerError::IoError(err.to_string()))
+            }
+            _ => (),
+        }
+        std::fs::create_dir_all(&directory)
+            .map_err(|err| RecordingManagerError::IoError(err.to_string()))?;
+
+        Ok(HlsOutput {
+            directory,
+            target_duration: hls.target_duration,
+            playlist_length: hls.playlist_length,
+            max_files: hls.max_files,
+        })
+    }
+
+    /// Requests a graceful stop: the pipeline is drained through EOS so the
+    /// container is properly finalized.
+    pub fn stop(&self, id: Uuid) -> Result<RecordingInfo, RecordingManagerError> {
+        let pipeline = {
+            let sessions = self.registry.sessions.lock().unwrap();
+            let session = sessions
+                .get(&id)
+                .ok_or(RecordingManagerError::RecordingNotFound(id))?;
+
+            match &session.pipeline {
+                Some(pipeline) if session.info.state.is_active() => pipeline.clone(),
+                _ => return Err(RecordingManagerError::RecordingNotActive(id)),
+            }
+        };
+
+        pipeline.send_event(gst::event::Eos::new());
+        self.registry
+            .update(id, |info| info.state = RecordingState::Finalizing);
+
+        self.get(id)
+    }
+
+    pub fn get(&self, id: Uuid) -> Result<RecordingInfo, RecordingManagerError> {
+        self.registry
+            .get(id)
+            .ok_or(RecordingManagerError::RecordingNotFound(id))
+    }
+
+    pub fn list(&self) -> Vec<RecordingInfo> {
+        let mut recordings: Vec<RecordingInfo> = self
+            .registry
+            .sessions
+            .lock()
+            .unwrap()
+            .values()
+            .map(|session| session.info.clone())
+            .collect();
+        recordings.sort_by_key(|info| info.started_at);
+
+        recordings
+    }
+}
+
+/// Drives the state machine of a single recording until it completes or fails.
+fn watch_bus(
+    registry: Arc<Registry>,
+    id: Uuid,
+    pipeline: gst::Pipeline,
+    bus: gst::Bus,
+    max_duration: Option<Duration>,
+) {
+    let deadline = max_duration.and_then(|duration| Instant::now().checked_add(duration));
+    let mut eos_sent = false;
+    let mut segments = SegmentTracker::default();
+
+    let outcome = loop {
+        if let Some(deadline) = deadline {
+            if !eos_sent && Instant::now() >= deadline {
+                pipeline.send_event(gst::event::Eos::new());
+                registry.update(id, |info| info.state = RecordingState::Finalizing);
+                eos_sent = true;
+            }
+        }
+
+        let Some(msg) = bus.timed_pop(BUS_POLL_INTERVAL) else {
+            continue;
+        };
+
+        use gst::MessageView;
+
+        match msg.view() {
+            MessageView::StateChanged(state_changed) => {
+                let from_pipeline = msg.src().map(|src| src == &pipeline).unwrap_or(false);
+                if from_pipeline && state_changed.current() == gst::State::Playing {
+                    registry.update(id, |info| {
+                        if info.state == RecordingState::Pending {
+                            info.state = RecordingState::Recording;
+                        }
+                    });
+                }
+            }
+            // hlssink2 splits its output with an internal splitmuxsink as
+            // well, only the recording sink produces segments
+            MessageView::Element(element) if is_from_element(&msg, "sink") => {
+                if let Some(structure) = element.structure() {
+                    segments.handle_message(&registry, id, structure);
+                }
+            }
+            MessageView::Eos(..) => break Ok(()),
+            MessageView::Error(err) => break Err(StreamError::from_error_message(err)),
+            MessageView::Warning(warning) => {
+                tracing::warn!(
+                    "Recording {} warning from {:?}: {} ({:?})",
+                    id,
+                    warning.src().map(|src| src.path_string()),
+                    warning.error(),
+                    warning.debug()
+                );
+            }
+            _ => (),
+        }
+    };
+
+    let position = pipeline.query_position::<gst::ClockTime>();
+    if let Err(err) = pipeline.set_state(gst::State::Null) {
+        tracing::warn!("Recording {} failed to release its pipeline: {}", id, err);
+    }
+
+    segments.finish(&registry, outcome.as_ref().err(), position);
+    // Segments get their own previews as they are closed
+    let has_previews = outcome.is_ok() && segments.count == 0;
+
+    registry.update(id, |info| {
+        let finished_at = Utc::now();
+        info.duration = position
+            .map(Duration::from)
+            .or_else(|| (finished_at - info.started_at).to_std().ok());
+        info.size = match segments.count {
+            0 => file_size(&info.location),
+            _ => Some(segments.total_size),
+        };
+        info.finished_at = Some(finished_at);
+
+        match outcome {
+            Ok(()) => info.state = RecordingState::Completed,
+            Err(error) => {
+                tracing::error!("Recording {} failed: {}", id, error);
+                info.state = RecordingState::Failed;
+                info.error = Some(error);
+            }
+        }
+    });
+
+    if has_previews {
+        registry.generate_previews(id);
+    }
+}
+
+fn is_from_element(msg: &gst::Message, name: &str) -> bool {
+    msg.src().is_some_and(|src| src.name() == name)
+}
+
+fn file_size(location: &Path) -> Option<u64> {
+    std::fs::metadata(location)
+        .map(|metadata| metadata.len())
+        .ok()
+}
+
+/// Follows the files written by a splitmuxsink, registering each one as a
+/// child recording of the session.
+#[derive(Default)]
+struct SegmentTracker {
+    /// Open segment with the running time at which it started.
+    current: Option<(Uuid, gst::ClockTime)>,
+    count: u32,
+    total_size: u64,
+}
+
+impl SegmentTracker {
+    fn handle_message(
+        &mut self,
+        registry: &Arc<Registry>,
+        parent_id: Uuid,
+        structure: &gst::StructureRef,
+    ) {
+        let running_time = structure
+            .get::<u64>("running-time")
+            .map(gst::ClockTime::from_nseconds)
+            .unwrap_or(gst::ClockTime::ZERO);
+
+        match structure.name().as_str() {
+            "splitmuxsink-fragment-opened" => {
+                let Ok(location) = structure.get::<String>("location") else {
+                    return;
+                };
+                let Some(parent) = registry.get(parent_id) else {
+                    return;
+                };
+
+                let segment_id = Uuid::new_v4();
+                registry.insert(RecordingSession {
+                    info: RecordingInfo {
+                        id: segment_id,
+                        parent_id: Some(parent_id),
+                        state: RecordingState::Recording,
+                        location: PathBuf::from(location),
+                        duration: None,
+                        size: None,
+                        started_at: Utc::now(),
+                        finished_at: None,
+                        error: None,
+                        previews: RecordingPreviews::default(),
+                        ..parent
+                    },
+                    pipeline: None,
+                });
+                self.current = Some((segment_id, running_time));
+                self.count += 1;
+            }
+            "splitmuxsink-fragment-closed" => {
+                if let Some((segment_id, opened_at)) = self.current.take() {
+                    self.close(
+                        registry,
+                        segment_id,
+                        Some(running_time.saturating_sub(opened_at)),
+                        None,
+                    );
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Closes the segment left open when the pipeline stopped.
+    fn finish(
+        &mut self,
+        registry: &Arc<Registry>,
+        error: Option<&StreamError>,
+        position: Option<gst::ClockTime>,
+    ) {
+        if let Some((segment_id, opened_at)) = self.current.take() {
+            let duration = position.map(|position| position.saturating_sub(opened_at));
+            self.close(registry, segment_id, duration, error.cloned());
+        }
+    }
+
+    fn close(
+        &mut self,
+        registry: &Arc<Registry>,
+        segment_id: Uuid,
+        duration: Option<gst::ClockTime>,
+        error: Option<StreamError>,
+    ) {
+        let completed = error.is_none();
+        registry.update(segment_id, |info| {
+            info.duration = duration.map(Duration::from);
+            info.size = file_size(&info.location);
+            info.finished_at = Some(Utc::now());
+            info.state = match error {
+                Some(_) => RecordingState::Failed,
+                None => RecordingState::Completed,
+            };
+            info.error = error;
+
+            self.total_size += info.size.unwrap_or(0);
+        });
+
+        if completed {
+            registry.generate_previews(segment_id);
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use gstreamer as gst;
+#[cfg(feature = "rtsp")]
+use gstreamer_rtsp_server::{
+    self as gst_rtsp_server,
+    prelude::{RTSPMediaFactoryExt, RTSPMountPointsExt, RTSPServerExt, RTSPServerExtManual},
+};
+use thiserror::Error;
+
+use crate::configuration::{MediaConfiguration, RtspConfiguration};
+
+use super::pipeline::{check_elements, encoder_launch_fragment, format_caps, PipelineError};
+use super::recording_manager::RecordingManager;
+use super::stream_error::StreamError;
+
+#[derive(Debug, Error)]
+pub enum RtspServerError {
+    #[error("RTSP support is not available, the server was built without the rtsp feature")]
+    Unsupported,
+    #[error("RTSP server is disabled")]
+    Disabled,
+    #[error("Source {0} is not configured")]
+    SourceNotFound(String),
+    #[error("Source {0} is already mounted")]
+    AlreadyMounted(String),
+    #[error("Source {0} is being captured, stop its recordings and previews first")]
+    SourceBusy(String),
+    #[error("Encoding profile {0} is not configured")]
+    ProfileNotFound(String),
+    #[error("Source {0} is not mounted")]
+    NotMounted(String),
+    #[error("Invalid pipeline: {0}")]
+    InvalidPipeline(#[from] PipelineError),
+    #[error(transparent)]
+    StreamError(#[from] StreamError),
+}
+
+/// Source exposed by the RTSP server.
+#[derive(Clone, Debug)]
+pub struct RtspMount {
+    pub source: String,
+    pub path: String,
+    pub url: String,
+}
+
+/// Embedded RTSP server exposing sources at `rtsp://host:port/<source>`.
+/// Each mount runs its own capture pipeline, shared by its clients, so a
+/// mounted source holds its capture device: it cannot be mounted while it is
+/// recorded, previewed or pre-roll buffered, and those fail until it is
+/// unmounted.
+#[derive(Clone)]
+pub struct RtspServer {
+    configuration: Arc<MediaConfiguration>,
+    mounts: Arc<Mutex<BTreeMap<String, RtspMount>>>,
+    #[cfg(feature = "rtsp")]
+    server: Arc<std::sync::OnceLock<gst_rtsp_server::RTSPServer>>,
+}
+
+impl RtspServer {
+    pub fn new(configuration: MediaConfiguration) -> Self {
+        Self {
+            configuration: Arc::new(configuration),
+            mounts: Arc::new(Mutex::new(BTreeMap::new())),
+            #[cfg(feature = "rtsp")]
+            server: Arc::new(std::sync::OnceLock::new()),
+        }
+    }
+
+    /// Starts listening when `media.rtsp` is configured, mounting the sources
+    /// if requested. Does nothing otherwise. Sources failing to mount are
+    /// logged and left out.
+    pub fn start(&self, manager: &RecordingManager) -> Result<Vec<RtspMount>, RtspServerError> {
+        let Some(rtsp) = self.rtsp_configuration() else {
+            return Ok(Vec::new());
+        };
+
+        gst::init().map_err(|err| StreamError::Initialization {
+            message: err.to_string(),
+        })?;
+        self.listen(rtsp)?;
+        tracing::info!("RTSP server listening on {}:{}", rtsp.address, rtsp.port);
+
+        if !rtsp.mount_sources {
+            return Ok(Vec::new());
+        }
+
+        let mounts = self
+            .configuration
+            .sources
+            .iter()
+            .filter_map(|source| {
+                self.mount(&source.name, manager)
+                    .inspect_err(|err| {
+                        tracing::error!("Failed to mount source {}: {}", source.name, err)
+                    })
+                    .ok()
+            })
+            .collect();
+
+        Ok(mounts)
+    }
+
+    /// Mounts `source`, claiming its capture device from `manager`.
+    pub fn mount(
+        &self,
+        source: &str,
+        manager: &RecordingManager,
+    ) -> Result<RtspMount, RtspServerError> {
+        let rtsp = self.rtsp_configuration().ok_or(RtspServerError::Disabled)?;
+        let source = self
+            .configuration
+            .find_source(Some(source))
+            .ok_or_else(|| RtspServerError::SourceNotFound(source.to_owned()))?;
+
+        let mut mounts = self.mounts.lock().unwrap();
+        if mounts.contains_key(&source.name) {
+            return Err(RtspServerError::AlreadyMounted(source.name.clone()));
+        }
+
+        let profile_name = rtsp
+            .profile
+            .as_deref()
+            .or(self.configuration.default_profile.as_deref());
+        let profile = self
+            .configuration
+            .find_profile(profile_name)
+            .ok_or_else(|| {
+                RtspServerError::ProfileNotFound(profile_name.unwrap_or_default().to_owned())
+            })?;
+        let encoder = profile.encoder;
+
+        let mut elements = source.video.element_names();
+        elements.push("videoconvert");
+        if profile.converts_format() {
+            elements.extend(["videoscale", "videorate", "capsfilter"]);
+        }
+        elements.extend([
+            encoder.element_name(),
+            encoder.parser_name(),
+            encoder.payloader_name(),
+        ]);
+        check_elements(&elements)?;
+
+        let mut launch = vec![source.video.launch_fragment(), String::from("videoconvert")];
+        if profile.converts_format() {
+            launch.extend([
+                String::from("videoscale"),
+                String::from("videorate"),
+                format!("capsfilter caps=\"{}\"", format_caps(&profile)),
+            ]);
+        }
+        launch.extend([
+            encoder_launch_fragment(&profile)?,
+            encoder.parser_name().to_owned(),
+            // The payloader must be named pay0 for the media factory to find it
+            format!("{} name=pay0 pt=96", encoder.payloader_name()),
+        ]);
+        let launch = format!("( {} )", launch.join(" ! "));
+
+        if !manager.claim_rtsp(&source.name) {
+            return Err(RtspServerError::SourceBusy(source.name.clone()));
+        }
+        let path = format!("/{}", source.name);
+        self.add_factory(&path, &launch)
+            .inspect_err(|_| manager.release_rtsp(&source.name))?;
+
+        let mount = RtspMount {
+            source: source.name.clone(),
+            url: format!(
+                "rtsp://{}:{}{}",
+                rtsp.host.as_deref().unwrap_or(&rtsp.address),
+                rtsp.port,
+                path
+            ),
+            path,
+        };
+        mounts.insert(source.name.clone(), mount.clone());
+
+        Ok(mount)
+    }
+
+    pub fn unmount(
+        &self,
+        source: &str,
+        manager: &RecordingManager,
+    ) -> Result<RtspMount, RtspServerError> {
+        let mount = self
+            .mounts
+            .lock()
+            .unwrap()
+            .remove(source)
+            .ok_or_else(|| RtspServerError::NotMounted(source.to_owned()))?;
+        self.remove_factory(&mount.path)?;
+        manager.release_rtsp(&mount.source);
+
+        Ok(mount)
+    }
+
+    pub fn list(&self) -> Vec<RtspMount> {
+        self.mounts.lock().unwrap().values().cloned().collect()
+    }
+
+    fn rtsp_configuration(&self) -> Option<&RtspConfiguration> {
+        self.configuration
+            .rtsp
+            .as_ref()
+            .filter(|_| self.configuration.enabled)
+    }
+
+    /// Binds the server and serves it from a dedicated GLib main loop.
+    #[cfg(feature = "rtsp")]
+    fn listen(&self, rtsp: &RtspConfiguration) -> Result<(), RtspServerError> {
+        use gst::glib;
+
+        let server = gst_rtsp_server::RTSPServer::new();
+        server.set_address(&rtsp.address);
+        server.set_service(&rtsp.port.to_string());
+
+        let context = glib::MainContext::new();
+        server
+            .attach(Some(&context))
+            .map_err(|err| StreamError::Other {
+                message: format!("Failed to start the RTSP server: {}", err),
+            })?;
+        std::thread::spawn(move || glib::MainLoop::new(Some(&context), false).run());
+
+        let _ = self.server.set(server);
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "rtsp"))]
+    fn listen(&self, _rtsp: &RtspConfiguration) -> Result<(), RtspServerError> {
+        Err(RtspServerError::Unsupported)
+    }
+
+    #[cfg(feature = "rtsp")]
+    fn add_factory(&self, path: &str, launch: &str) -> Result<(), RtspServerError> {
+        let mount_points = self.mount_points()?;
+
+        let factory = gst_rtsp_server::RTSPMediaFactory::new();
+        factory.set_launch(launch);
+        factory.set_shared(true);
+        mount_points.add_factory(path, factory);
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "rtsp"))]
+    fn add_factory(&self, _path: &str, _launch: &str) -> Result<(), RtspServerError> {
+        Err(RtspServerError::Unsupported)
+    }
+
+    #[cfg(feature = "rtsp")]
+    fn remove_factory(&self, path: &str) -> Result<(), RtspServerError> {
+        self.mount_points()?.remove_factory(path);
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "rtsp"))]
+    fn remove_factory(&self, _path: &str) -> Result<(), RtspServerError> {
+        Err(RtspServerError::Unsupported)
+    }
+
+    #[cfg(feature = "rtsp")]
+    fn mount_points(&self) -> Result<gst_rtsp_server::RTSPMountPoints, RtspServerError> {
+        self.server
+            .get()
+            .and_then(|server| server.mount_points())
+            .ok_or(RtspServerError::Disabled)
+    }
+}
@@ -0,0 +1,215 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use axum::body::Bytes;
+use gstreamer as gst;
+use gstreamer::prelude::{ElementExt, GstBinExt, ObjectExt};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::configuration::SourceConfiguration;
+
+use super::pipeline::{add_chain, check_elements, make_element, PipelineError};
+use super::recording_manager::RecordingManagerError;
+use super::stream_error::StreamError;
+
+/// Name of the appsink holding the latest raw frame of a live pipeline.
+pub const SNAPSHOT_SINK: &str = "snapshot_sink";
+
+/// Elements of the snapshot tap, fed with raw video.
+pub const SNAPSHOT_TAP_ELEMENTS: [&str; 2] = ["queue", "appsink"];
+
+/// How long a snapshot pipeline may take to produce its frame.
+const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Frames skipped by an idle capture, letting cameras adjust their exposure.
+const WARMUP_FRAMES: u32 = 5;
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SnapshotFormat {
+    #[default]
+    Jpeg,
+    Png,
+}
+
+impl SnapshotFormat {
+    pub fn element_name(&self) -> &'static str {
+        match self {
+            SnapshotFormat::Jpeg => "jpegenc",
+            SnapshotFormat::Png => "pngenc",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SnapshotFormat::Jpeg => "jpeg",
+            SnapshotFormat::Png => "png",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            SnapshotFormat::Jpeg => "image/jpeg",
+            SnapshotFormat::Png => "image/png",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SnapshotFormat::Jpeg => "jpg",
+            SnapshotFormat::Png => "png",
+        }
+    }
+}
+
+impl FromStr for SnapshotFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "jpeg" => Ok(SnapshotFormat::Jpeg),
+            "png" => Ok(SnapshotFormat::Png),
+            _ => Err(format!("Unknown snapshot format: {}", value)),
+        }
+    }
+}
+
+/// Creates the snapshot tap of a live pipeline, an appsink whose
+/// `last-sample` is the latest raw frame:
+/// queue ! appsink
+pub(super) fn build_snapshot_tap() -> Result<Vec<gst::Element>, PipelineError> {
+    let queue = gst::ElementFactory::make("queue")
+        .name("snapshot_queue")
+        .property("max-size-buffers", 1u32)
+        .property_from_str("leaky", "downstream")
+        .build()
+        .map_err(|err| PipelineError::ElementCreation("queue".into(), err.to_string()))?;
+    let sink = gst::ElementFactory::make("appsink")
+        .name(SNAPSHOT_SINK)
+        .property("max-buffers", 1u32)
+        .property("drop", true)
+        .property("sync", false)
+        .property("enable-last-sample", true)
+        .build()
+        .map_err(|err| PipelineError::ElementCreation("appsink".into(), err.to_string()))?;
+
+    Ok(vec![queue, sink])
+}
+
+/// Latest raw frame seen by the snapshot tap of `pipeline`.
+pub(super) fn last_frame(pipeline: &gst::Pipeline) -> Option<gst::Sample> {
+    pipeline
+        .by_name(SNAPSHOT_SINK)?
+        .property::<Option<gst::Sample>>("last-sample")
+}
+
+/// Encodes a raw frame taken from a live pipeline:
+/// appsrc ! videoconvert ! encoder ! appsink
+pub(super) fn encode_frame(
+    frame: &gst::Sample,
+    format: SnapshotFormat,
+) -> Result<Bytes, RecordingManagerError> {
+    check_elements(&["appsrc", "videoconvert", format.element_name(), "appsink"])?;
+
+    let source = gst::ElementFactory::make("appsrc")
+        .name("source")
+        .property("caps", frame.caps_owned())
+        .property_from_str("format", "time")
+        .build()
+        .map_err(|err| PipelineError::ElementCreation("appsrc".into(), err.to_string()))?;
+    let convert = make_element("videoconvert", "convert")?;
+    let (pipeline, sink) = build_encoder_pipeline(vec![source.clone(), convert], format)?;
+
+    run_pipeline(&pipeline, || {
+        let _ = source.emit_by_name::<gst::FlowReturn>("push-sample", &[frame]);
+        let _ = source.emit_by_name::<gst::FlowReturn>("end-of-stream", &[]);
+
+        pull_frame(&sink)
+    })
+}
+
+/// Captures a frame from a source no pipeline is running for:
+/// source ! videoconvert ! encoder ! appsink
+pub(super) fn capture_frame(
+    source: &SourceConfiguration,
+    format: SnapshotFormat,
+) -> Result<Bytes, RecordingManagerError> {
+    let mut elements = source.video.element_names();
+    elements.extend(["videoconvert", format.element_name(), "appsink"]);
+    check_elements(&elements)?;
+
+    let convert = make_element("videoconvert", "convert")?;
+    let (pipeline, sink) = build_encoder_pipeline(vec![convert.clone()], format)?;
+    source.video.build(&pipeline, &convert)?;
+
+    run_pipeline(&pipeline, || {
+        for _ in 0..WARMUP_FRAMES {
+            pull_frame(&sink)?;
+        }
+
+        pull_frame(&sink)
+    })
+}
+
+/// Chains `elements` producing raw video to the encoder of `format` and an
+/// appsink.
+fn build_encoder_pipeline(
+    mut elements: Vec<gst::Element>,
+    format: SnapshotFormat,
+) -> Result<(gst::Pipeline, gst::Element), PipelineError> {
+    let pipeline = gst::Pipeline::new();
+    let sink = gst::ElementFactory::make("appsink")
+        .name("sink")
+        .property("sync", false)
+        .build()
+        .map_err(|err| PipelineError::ElementCreation("appsink".into(), err.to_string()))?;
+
+    elements.extend([
+        make_element(format.element_name(), "encoder")?,
+        sink.clone(),
+    ]);
+    add_chain(&pipeline, &elements)?;
+
+    Ok((pipeline, sink))
+}
+
+/// Plays `pipeline` for the duration of `capture`, reporting the pipeline
+/// error when no frame comes out.
+fn run_pipeline(
+    pipeline: &gst::Pipeline,
+    capture: impl FnOnce() -> Option<Bytes>,
+) -> Result<Bytes, RecordingManagerError> {
+    if let Err(err) = pipeline.set_state(gst::State::Playing) {
+        let _ = pipeline.set_state(gst::State::Null);
+        return Err(StreamError::state_change(gst::State::Playing, err).into());
+    }
+
+    let frame = capture();
+    let error = pipeline
+        .bus()
+        .and_then(|bus| bus.pop_filtered(&[gst::MessageType::Error]))
+        .and_then(|msg| match msg.view() {
+            gst::MessageView::Error(err) => Some(StreamError::from_error_message(err)),
+            _ => None,
+        });
+    let _ = pipeline.set_state(gst::State::Null);
+
+    match (frame, error) {
+        (Some(frame), _) => Ok(frame),
+        (None, Some(error)) => Err(error.into()),
+        (None, None) => Err(StreamError::Other {
+            message: String::from("No frame captured before the timeout"),
+        }
+        .into()),
+    }
+}
+
+fn pull_frame(sink: &gst::Element) -> Option<Bytes> {
+    let timeout = SNAPSHOT_TIMEOUT.as_nanos() as u64;
+    let sample = sink.emit_by_name::<Option<gst::Sample>>("try-pull-sample", &[&timeout])?;
+    let buffer = sample.buffer()?;
+    let map = buffer.map_readable().ok()?;
+
+    Some(Bytes::copy_from_slice(map.as_slice()))
+}
@@ -0,0 +1,59 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::motion::{MotionEvent, MotionTransition};
+use super::rtsp_server::RtspMount;
+use super::snapshot::SnapshotFormat;
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct RtspMountDto {
+    pub source: String,
+    pub path: String,
+    /// URL to hand to RTSP clients such as VLC or an NVR.
+    pub url: String,
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct SnapshotCreateDto {
+    /// Image format, JPEG when omitted.
+    pub format: Option<SnapshotFormat>,
+    /// Keeps the image as an asset listed under `/api/snapshots`.
+    #[serde(default)]
+    pub persist: bool,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct MotionEventDto {
+    pub source: String,
+    /// `started` or `ended`.
+    pub kind: String,
+    /// Fraction of the watched pixels that changed, set when motion started.
+    pub score: Option<f64>,
+    pub at: DateTime<Utc>,
+    pub recording_id: Option<Uuid>,
+}
+
+pub fn get_motion_event_dto(event: MotionEvent) -> MotionEventDto {
+    let (kind, score) = match event.transition {
+        MotionTransition::Started { score } => ("started", Some(score)),
+        MotionTransition::Ended => ("ended", None),
+    };
+
+    MotionEventDto {
+        source: event.source,
+        kind: kind.to_owned(),
+        score,
+        at: event.at,
+        recording_id: event.recording_id,
+    }
+}
+
+pub fn get_rtsp_mount_dto(mount: RtspMount) -> RtspMountDto {
+    RtspMountDto {
+        source: mount.source,
+        path: mount.path,
+        url: mount.url,
+    }
+}
@@ -0,0 +1,50 @@
+use gstreamer as gst;
+use gstreamer::prelude::GstObjectExt;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Runtime failure of a stream pipeline. It is recorded on the failed
+/// recording, so it is serializable and does not hold GStreamer objects.
+#[derive(Clone, Debug, Error, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum StreamError {
+    #[error("GStreamer initialization failed: {message}")]
+    Initialization { message: String },
+    #[error("Failed to set pipeline to {state}: {message}")]
+    StateChange { state: String, message: String },
+    #[error("Error from {}: {message} ({domain})", element.as_deref().unwrap_or("pipeline"))]
+    Element {
+        /// Path of the element posting the error, e.g. `/GstPipeline:pipeline0/GstX264Enc:encoder`.
+        element: Option<String>,
+        /// GError domain, e.g. `gst-resource-error-quark`.
+        domain: String,
+        message: String,
+        debug: Option<String>,
+    },
+    /// The process exited before the recording was finalized.
+    #[error("Recording was interrupted before it was finalized")]
+    Interrupted,
+    #[error("{message}")]
+    Other { message: String },
+}
+
+impl StreamError {
+    pub fn from_error_message(message: &gst::message::Error) -> Self {
+        let error = message.error();
+
+        StreamError::Element {
+            element: message.src().map(|src| src.path_string().to_string()),
+            domain: error.domain().as_str().to_string(),
+            message: error.message().to_owned(),
+            debug: message.debug().map(|debug| debug.to_string()),
+        }
+    }
+
+    pub fn state_change(state: gst::State, error: gst::StateChangeError) -> Self {
+        StreamError::StateChange {
+            state: format!("{:?}", state),
+            message: error.to_string(),
+        }
+    }
+}
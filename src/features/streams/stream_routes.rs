@@ -0,0 +1,280 @@
+use std::convert::Infallible;
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, State},
+    http::{header, StatusCode},
+    middleware,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{get, post, put},
+    Json, Router,
+};
+
+use tokio_stream::{wrappers::BroadcastStream, StreamExt};
+
+use crate::{
+    application::ApplicationState,
+    features::{
+        auth::{
+            auth_dto::get_preview_token_dto,
+            auth_entity::AuthenticatedUser,
+            auth_middleware::{authenticate_preview, require_role},
+            auth_service::{AuthService, AuthServiceError},
+        },
+        users::user_entity::Role,
+    },
+    service::ServiceType,
+};
+
+use super::{
+    rtsp_server::RtspServerError,
+    stream_dto::{get_motion_event_dto, get_rtsp_mount_dto, RtspMountDto, SnapshotCreateDto},
+    stream_service::{StreamService, StreamServiceError},
+};
+
+/// Separates the JPEG frames of the MJPEG preview.
+const PREVIEW_BOUNDARY: &str = "frame";
+
+impl IntoResponse for StreamServiceError {
+    fn into_response(self) -> Response {
+        let status_code = match self {
+            StreamServiceError::MediaDisabled | StreamServiceError::HlsDisabled => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            StreamServiceError::SourceNotFound(_)
+            | StreamServiceError::SourceNotLive(_)
+            | StreamServiceError::FileNotFound(_) => StatusCode::NOT_FOUND,
+            StreamServiceError::InvalidBody(_) => StatusCode::BAD_REQUEST,
+            StreamServiceError::IoError(_)
+            | StreamServiceError::SnapshotFailed(_)
+            | StreamServiceError::PreviewFailed(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            StreamServiceError::SnapshotServiceError(err) => return err.into_response(),
+            StreamServiceError::RecordingManagerError(err) => return err.into_response(),
+            StreamServiceError::RtspServerError(err) => return err.into_response(),
+        };
+
+        let body = axum::Json(serde_json::json!({ "error": self.to_string() }));
+        (status_code, body).into_response()
+    }
+}
+
+impl IntoResponse for RtspServerError {
+    fn into_response(self) -> Response {
+        let status_code = match self {
+            RtspServerError::Unsupported | RtspServerError::Disabled => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            RtspServerError::SourceNotFound(_) | RtspServerError::NotMounted(_) => {
+                StatusCode::NOT_FOUND
+            }
+            RtspServerError::AlreadyMounted(_) | RtspServerError::SourceBusy(_) => {
+                StatusCode::CONFLICT
+            }
+            RtspServerError::ProfileNotFound(_)
+            | RtspServerError::InvalidPipeline(_)
+            | RtspServerError::StreamError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        let body = axum::Json(serde_json::json!({ "error": self.to_string() }));
+        (status_code, body).into_response()
+    }
+}
+
+pub fn routes(state: ApplicationState) -> Router {
+    let stream_service = match state.service_provider.get_service("StreamService") {
+        Some(ServiceType::StreamService(stream_service)) => stream_service,
+        _ => panic!("StreamService not found in ServiceProvider"),
+    };
+    let auth_service = match state.service_provider.get_service("AuthService") {
+        Some(ServiceType::AuthService(auth_service)) => auth_service,
+        _ => panic!("AuthService not found in ServiceProvider"),
+    };
+
+    // Viewers can only watch
+    let operator_routes = Router::new()
+        .route(
+            "/:source/rtsp",
+            put(handle_mount_rtsp).delete(handle_unmount_rtsp),
+        )
+        .route("/:source/snapshot", post(handle_take_snapshot))
+        .route_layer(middleware::from_fn_with_state(Role::Operator, require_role));
+
+    // Browsers cannot send headers from <img>, so the preview also accepts
+    // a preview token in its query
+    let preview_routes = Router::new()
+        .route("/:source/preview.mjpeg", get(handle_preview))
+        .route_layer(middleware::from_fn_with_state(Role::Viewer, require_role))
+        .route_layer(middleware::from_fn_with_state(
+            auth_service.clone(),
+            authenticate_preview,
+        ));
+
+    let preview_token_routes = Router::new()
+        .route("/:source/preview-token", post(handle_issue_preview_token))
+        .route_layer(middleware::from_fn_with_state(Role::Viewer, require_role))
+        .with_state(auth_service);
+
+    Router::new()
+        .route("/rtsp", get(handle_list_rtsp_mounts))
+        .route("/motion/events", get(handle_motion_events))
+        .route("/:source/hls/:file", get(handle_read_hls_file))
+        .route_layer(middleware::from_fn_with_state(Role::Viewer, require_role))
+        .merge(operator_routes)
+        .merge(preview_routes)
+        .with_state(stream_service)
+        .merge(preview_token_routes)
+}
+
+async fn handle_issue_preview_token(
+    State(service): State<AuthService>,
+    Path(source): Path<String>,
+    user: AuthenticatedUser,
+) -> Result<impl IntoResponse, AuthServiceError> {
+    let token = service.issue_preview_token(&user, &source)?;
+
+    Ok((StatusCode::OK, Json(get_preview_token_dto(token))))
+}
+
+async fn handle_read_hls_file(
+    State(service): State<StreamService>,
+    Path((source, file)): Path<(String, String)>,
+) -> Result<impl IntoResponse, StreamServiceError> {
+    let stream_file = service.read_hls_file(&source, &file).await?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, stream_file.content_type),
+            // The playlist changes with every segment
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        stream_file.content,
+    ))
+}
+
+async fn handle_list_rtsp_mounts(State(service): State<StreamService>) -> impl IntoResponse {
+    let mount_dtos: Vec<RtspMountDto> = service
+        .list_rtsp_mounts()
+        .into_iter()
+        .map(get_rtsp_mount_dto)
+        .collect();
+
+    Json(mount_dtos)
+}
+
+async fn handle_mount_rtsp(
+    State(service): State<StreamService>,
+    Path(source): Path<String>,
+) -> Result<impl IntoResponse, StreamServiceError> {
+    let mount = service.mount_rtsp(&source)?;
+
+    Ok((StatusCode::CREATED, Json(get_rtsp_mount_dto(mount))))
+}
+
+async fn handle_unmount_rtsp(
+    State(service): State<StreamService>,
+    Path(source): Path<String>,
+) -> Result<impl IntoResponse, StreamServiceError> {
+    service.unmount_rtsp(&source)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn handle_preview(
+    State(service): State<StreamService>,
+    Path(source): Path<String>,
+) -> Result<impl IntoResponse, StreamServiceError> {
+    let frames = service.subscribe_preview(&source).await?;
+
+    // Frames missed by a slow client are skipped
+    let parts = BroadcastStream::new(frames)
+        .filter_map(|frame| frame.ok())
+        .map(|frame| Ok::<_, Infallible>(get_multipart_frame(&frame)));
+
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                format!("multipart/x-mixed-replace; boundary={}", PREVIEW_BOUNDARY),
+            ),
+            (header::CACHE_CONTROL, String::from("no-cache")),
+        ],
+        Body::from_stream(parts),
+    ))
+}
+
+async fn handle_motion_events(
+    State(service): State<StreamService>,
+) -> Result<impl IntoResponse, StreamServiceError> {
+    let motion_events = service.subscribe_motion()?;
+
+    // Events missed by a slow client are skipped
+    let events = BroadcastStream::new(motion_events)
+        .filter_map(|event| event.ok())
+        .map(|event| {
+            let event_dto = get_motion_event_dto(event);
+            Event::default()
+                .event(event_dto.kind.clone())
+                .json_data(&event_dto)
+        });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+async fn handle_take_snapshot(
+    State(service): State<StreamService>,
+    user: AuthenticatedUser,
+    Path(source): Path<String>,
+    body: Bytes,
+) -> Result<impl IntoResponse, StreamServiceError> {
+    // The body is optional, but one that is sent must be valid
+    let snapshot_dto = match body.is_empty() {
+        true => SnapshotCreateDto::default(),
+        false => {
+            let Json(snapshot_dto) = Json::<SnapshotCreateDto>::from_bytes(&body)
+                .map_err(|err| StreamServiceError::InvalidBody(err.body_text()))?;
+            snapshot_dto
+        }
+    };
+    let snapshot = service
+        .take_snapshot(
+            &source,
+            snapshot_dto.format.unwrap_or_default(),
+            snapshot_dto.persist,
+            Some(user.id),
+        )
+        .await?;
+
+    let mut headers = vec![(
+        header::CONTENT_TYPE,
+        snapshot.format.content_type().to_owned(),
+    )];
+    let status_code = match &snapshot.snapshot {
+        Some(asset) => {
+            headers.push((header::LOCATION, format!("/api/snapshots/{}", asset.id)));
+            StatusCode::CREATED
+        }
+        None => StatusCode::OK,
+    };
+
+    Ok((
+        status_code,
+        axum::response::AppendHeaders(headers),
+        snapshot.content,
+    ))
+}
+
+fn get_multipart_frame(frame: &Bytes) -> Bytes {
+    let mut part = format!(
+        "--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+        PREVIEW_BOUNDARY,
+        frame.len()
+    )
+    .into_bytes();
+    part.extend_from_slice(frame);
+    part.extend_from_slice(b"\r\n");
+
+    Bytes::from(part)
+}
@@ -0,0 +1,214 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use axum::body::Bytes;
+use thiserror::Error;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::configuration::MediaConfiguration;
+use crate::features::snapshots::{
+    snapshot_entity::Snapshot,
+    snapshot_service::{SnapshotService, SnapshotServiceError},
+};
+use crate::service::Service;
+
+use super::motion::MotionEvent;
+use super::recording_manager::{RecordingManager, RecordingManagerError};
+use super::rtsp_server::{RtspMount, RtspServer, RtspServerError};
+use super::snapshot::SnapshotFormat;
+
+#[derive(Debug, Error)]
+pub enum StreamServiceError {
+    #[error("Media capture is disabled")]
+    MediaDisabled,
+    #[error("Source {0} is not configured")]
+    SourceNotFound(String),
+    #[error("HLS output is disabled")]
+    HlsDisabled,
+    #[error("Source {0} is not live, start a recording to stream it")]
+    SourceNotLive(String),
+    #[error("Stream file {0} not found")]
+    FileNotFound(String),
+    #[error("I/O error: {0}")]
+    IoError(String),
+    #[error(transparent)]
+    RecordingManagerError(#[from] RecordingManagerError),
+    #[error(transparent)]
+    RtspServerError(#[from] RtspServerError),
+    #[error(transparent)]
+    SnapshotServiceError(#[from] SnapshotServiceError),
+    #[error("Snapshot capture failed: {0}")]
+    SnapshotFailed(String),
+    #[error("Preview failed: {0}")]
+    PreviewFailed(String),
+    #[error("Invalid request body: {0}")]
+    InvalidBody(String),
+}
+
+/// File of a live stream with its content type.
+pub struct StreamFile {
+    pub content: Vec<u8>,
+    pub content_type: &'static str,
+}
+
+/// Still frame of a source, with the asset it was kept as if requested.
+pub struct StreamSnapshot {
+    pub content: Bytes,
+    pub format: SnapshotFormat,
+    pub snapshot: Option<Snapshot>,
+}
+
+/// Live views of the configured sources, fed by the running recordings.
+#[derive(Clone)]
+pub struct StreamService {
+    pub name: String,
+    configuration: Arc<MediaConfiguration>,
+    manager: RecordingManager,
+    rtsp_server: RtspServer,
+    snapshot_service: SnapshotService,
+}
+
+impl Service for StreamService {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl StreamService {
+    pub fn new(
+        configuration: MediaConfiguration,
+        manager: RecordingManager,
+        snapshot_service: SnapshotService,
+    ) -> Self {
+        Self {
+            name: String::from("StreamService"),
+            rtsp_server: RtspServer::new(configuration.clone()),
+            configuration: Arc::new(configuration),
+            manager,
+            snapshot_service,
+        }
+    }
+
+    /// Subscribes to the JPEG frames of the preview of `source`.
+    pub async fn subscribe_preview(
+        &self,
+        source: &str,
+    ) -> Result<broadcast::Receiver<Bytes>, StreamServiceError> {
+        let manager = self.manager.clone();
+        let source_name = source.to_owned();
+        // Starting the preview pipeline waits on its state change
+        let frames = tokio::task::spawn_blocking(move || manager.subscribe_preview(&source_name))
+            .await
+            .map_err(|err| StreamServiceError::PreviewFailed(err.to_string()))??;
+
+        Ok(frames)
+    }
+
+    /// Subscribes to the motion events of every source watched for motion.
+    pub fn subscribe_motion(&self) -> Result<broadcast::Receiver<MotionEvent>, StreamServiceError> {
+        if !self.configuration.enabled {
+            return Err(StreamServiceError::MediaDisabled);
+        }
+
+        Ok(self.manager.subscribe_motion())
+    }
+
+    /// Grabs the latest frame of `source`, keeping it as a snapshot asset
+    /// when `persist` is set.
+    pub async fn take_snapshot(
+        &self,
+        source: &str,
+        format: SnapshotFormat,
+        persist: bool,
+        owner_id: Option<Uuid>,
+    ) -> Result<StreamSnapshot, StreamServiceError> {
+        let manager = self.manager.clone();
+        let source_name = source.to_owned();
+        // Capturing blocks until the pipeline produced a frame
+        let content = tokio::task::spawn_blocking(move || manager.snapshot(&source_name, format))
+            .await
+            .map_err(|err| StreamServiceError::SnapshotFailed(err.to_string()))??;
+
+        let snapshot = match persist {
+            true => Some(
+                self.snapshot_service
+                    .save_snapshot(source, format, &content, owner_id)
+                    .await?,
+            ),
+            false => None,
+        };
+
+        Ok(StreamSnapshot {
+            content,
+            format,
+            snapshot,
+        })
+    }
+
+    /// Starts the RTSP server when it is configured.
+    pub fn start_rtsp_server(&self) -> Result<Vec<RtspMount>, StreamServiceError> {
+        Ok(self.rtsp_server.start(&self.manager)?)
+    }
+
+    pub fn list_rtsp_mounts(&self) -> Vec<RtspMount> {
+        self.rtsp_server.list()
+    }
+
+    pub fn mount_rtsp(&self, source: &str) -> Result<RtspMount, StreamServiceError> {
+        Ok(self.rtsp_server.mount(source, &self.manager)?)
+    }
+
+    pub fn unmount_rtsp(&self, source: &str) -> Result<RtspMount, StreamServiceError> {
+        Ok(self.rtsp_server.unmount(source, &self.manager)?)
+    }
+
+    /// Reads the HLS playlist or one of its segments for `source`.
+    pub async fn read_hls_file(
+        &self,
+        source: &str,
+        file_name: &str,
+    ) -> Result<StreamFile, StreamServiceError> {
+        if !self.configuration.enabled {
+            return Err(StreamServiceError::MediaDisabled);
+        }
+        let source = self
+            .configuration
+            .find_source(Some(source))
+            .ok_or_else(|| StreamServiceError::SourceNotFound(source.to_owned()))?;
+        let directory = self
+            .manager
+            .hls_directory(&source.name)
+            .ok_or(StreamServiceError::HlsDisabled)?;
+        if !self.manager.is_live(&source.name) {
+            return Err(StreamServiceError::SourceNotLive(source.name.clone()));
+        }
+
+        // Only plain playlist and segment names are served from the directory
+        let content_type = match Path::new(file_name)
+            .extension()
+            .and_then(|ext| ext.to_str())
+        {
+            Some("m3u8") => "application/vnd.apple.mpegurl",
+            Some("ts") => "video/mp2t",
+            _ => return Err(StreamServiceError::FileNotFound(file_name.to_owned())),
+        };
+        if file_name.starts_with('.') || file_name.contains(['/', '\\']) {
+            return Err(StreamServiceError::FileNotFound(file_name.to_owned()));
+        }
+
+        let content = tokio::fs::read(directory.join(file_name))
+            .await
+            .map_err(|err| match err.kind() {
+                std::io::ErrorKind::NotFound => {
+                    StreamServiceError::FileNotFound(file_name.to_owned())
+                }
+                _ => StreamServiceError::IoError(err.to_string()),
+            })?;
+
+        Ok(StreamFile {
+            content,
+            content_type,
+        })
+    }
+}
@@ -0,0 +1,172 @@
+use std::path::{Path, PathBuf};
+
+use gstreamer as gst;
+use gstreamer::prelude::{ElementExt, ElementExtManual, ObjectExt};
+
+use crate::configuration::ThumbnailConfiguration;
+
+use super::output_path::companion_path;
+use super::pipeline::{add_chain, check_elements, make_element, PipelineError};
+use super::recording_manager::RecordingManagerError;
+use super::stream_error::StreamError;
+use super::video_source::VideoSource;
+
+/// How long the pipeline may take to preroll a frame after each seek.
+const PREROLL_TIMEOUT: gst::ClockTime = gst::ClockTime::from_seconds(10);
+
+/// Images extracted from a finished recording, stored next to its file.
+#[derive(Clone, Debug, Default)]
+pub struct RecordingPreviews {
+    pub poster: Option<PathBuf>,
+    pub thumbnails: Vec<PathBuf>,
+}
+
+/// Extracts the poster frame and `configuration.count` evenly spaced
+/// thumbnails of the recording at `location`:
+/// filesrc ! decodebin ! videoconvert ! videoscale ! capsfilter ! jpegenc ! appsink
+///
+/// The poster is taken at a tenth of the recording, past the first frames
+/// cameras need to settle. Blocks until every image is written.
+pub fn generate_previews(
+    location: &Path,
+    configuration: &ThumbnailConfiguration,
+) -> Result<RecordingPreviews, RecordingManagerError> {
+    let source = VideoSource::File {
+        location: location.display().to_string(),
+    };
+    let mut elements = source.element_names();
+    elements.extend([
+        "videoconvert",
+        "videoscale",
+        "capsfilter",
+        "jpegenc",
+        "appsink",
+    ]);
+    check_elements(&elements)?;
+
+    let pipeline = gst::Pipeline::new();
+    let convert = make_element("videoconvert", "convert")?;
+    // Only the width is fixed, videoscale keeps the aspect ratio
+    let caps = gst::Caps::builder("video/x-raw")
+        .field("width", configuration.width as i32)
+        .field("pixel-aspect-ratio", gst::Fraction::new(1, 1))
+        .build();
+    let capsfilter = gst::ElementFactory::make("capsfilter")
+        .name("caps")
+        .property("caps", caps)
+        .build()
+        .map_err(|err| PipelineError::ElementCreation("capsfilter".into(), err.to_string()))?;
+    let encoder = gst::ElementFactory::make("jpegenc")
+        .name("encoder")
+        .property("quality", configuration.quality as i32)
+        .build()
+        .map_err(|err| PipelineError::ElementCreation("jpegenc".into(), err.to_string()))?;
+    let sink = gst::ElementFactory::make("appsink")
+        .name("sink")
+        .property("sync", false)
+        .build()
+        .map_err(|err| PipelineError::ElementCreation("appsink".into(), err.to_string()))?;
+    add_chain(
+        &pipeline,
+        &[
+            convert.clone(),
+            make_element("videoscale", "scale")?,
+            capsfilter,
+            encoder,
+            sink.clone(),
+        ],
+    )?;
+    source.build(&pipeline, &convert)?;
+
+    let result = extract_previews(&pipeline, &sink, location, configuration);
+    let _ = pipeline.set_state(gst::State::Null);
+
+    result
+}
+
+fn extract_previews(
+    pipeline: &gst::Pipeline,
+    sink: &gst::Element,
+    location: &Path,
+    configuration: &ThumbnailConfiguration,
+) -> Result<RecordingPreviews, RecordingManagerError> {
+    pipeline
+        .set_state(gst::State::Paused)
+        .map_err(|err| StreamError::state_change(gst::State::Paused, err))?;
+    wait_for_preroll(pipeline)?;
+
+    let duration =
+        pipeline
+            .query_duration::<gst::ClockTime>()
+            .ok_or_else(|| StreamError::Other {
+                message: String::from("Recording duration is unknown"),
+            })?;
+
+    let poster = companion_path(location, "poster", "jpg");
+    write_frame_at(pipeline, sink, duration / 10, &poster)?;
+
+    let count = u64::from(configuration.count);
+    let mut thumbnails = Vec::with_capacity(configuration.count as usize);
+    for index in 1..=count {
+        let thumbnail = companion_path(location, &format!("thumb-{:02}", index), "jpg");
+        write_frame_at(pipeline, sink, duration * index / (count + 1), &thumbnail)?;
+        thumbnails.push(thumbnail);
+    }
+
+    Ok(RecordingPreviews {
+        poster: Some(poster),
+        thumbnails,
+    })
+}
+
+/// Seeks to the key frame closest to `position` and writes it to `path`.
+fn write_frame_at(
+    pipeline: &gst::Pipeline,
+    sink: &gst::Element,
+    position: gst::ClockTime,
+    path: &Path,
+) -> Result<(), RecordingManagerError> {
+    pipeline
+        .seek_simple(gst::SeekFlags::FLUSH | gst::SeekFlags::KEY_UNIT, position)
+        .map_err(|err| StreamError::Other {
+            message: format!("Failed to seek to {}: {}", position, err),
+        })?;
+    wait_for_preroll(pipeline)?;
+
+    let frame = sink
+        .emit_by_name::<Option<gst::Sample>>("pull-preroll", &[])
+        .and_then(|sample| sample.buffer_owned())
+        .ok_or_else(|| StreamError::Other {
+            message: format!("No frame at {}", position),
+        })?;
+    let map = frame.map_readable().map_err(|err| StreamError::Other {
+        message: err.to_string(),
+    })?;
+
+    std::fs::write(path, map.as_slice())
+        .map_err(|err| RecordingManagerError::IoError(err.to_string()))
+}
+
+/// Waits for the pipeline to reach the paused state, reporting the pipeline
+/// error when it does not.
+fn wait_for_preroll(pipeline: &gst::Pipeline) -> Result<(), RecordingManagerError> {
+    match pipeline.state(PREROLL_TIMEOUT).0 {
+        Ok(gst::StateChangeSuccess::Async) => Err(StreamError::Other {
+            message: String::from("Timed out waiting for a frame"),
+        }
+        .into()),
+        Ok(_) => Ok(()),
+        Err(err) => {
+            let error = pipeline
+                .bus()
+                .and_then(|bus| bus.pop_filtered(&[gst::MessageType::Error]))
+                .and_then(|msg| match msg.view() {
+                    gst::MessageView::Error(err) => Some(StreamError::from_error_message(err)),
+                    _ => None,
+                })
+                .unwrap_or_else(|| StreamError::state_change(gst::State::Paused, err));
+
+            Err(error.into())
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use gstreamer as gst;
+use gstreamer::prelude::{ElementExt, GstBinExt, GstBinExtManual, GstObjectExt, ObjectExt, PadExt};
+use serde::Deserialize;
+
+use super::pipeline::{link_elements, make_element, set_property_from_str, PipelineError};
+
+/// Video capture element feeding a pipeline, selected per source in
+/// `media.sources`. The `type` key uses the GStreamer element name.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum VideoSource {
+    /// Video4Linux2 devices: USB webcams, the legacy Raspberry Pi camera stack.
+    #[serde(rename = "v4l2src")]
+    V4l2 { device: Option<String> },
+    /// libcamera devices, the current Raspberry Pi camera stack.
+    #[serde(rename = "libcamerasrc")]
+    Libcamera { camera_name: Option<String> },
+    /// Network camera exposing an RTSP stream.
+    #[serde(rename = "rtspsrc")]
+    Rtsp {
+        location: String,
+        latency: Option<u32>,
+    },
+    /// Local media file, decoded and re-encoded.
+    #[serde(rename = "filesrc")]
+    File { location: String },
+    /// Synthetic test pattern, available everywhere GStreamer is.
+    #[serde(rename = "videotestsrc")]
+    Test { pattern: Option<String> },
+}
+
+impl VideoSource {
+    /// GStreamer elements needed to produce raw video from this source.
+    pub fn element_names(&self) -> Vec<&'static str> {
+        match self {
+            VideoSource::V4l2 { .. } => vec!["v4l2src"],
+            VideoSource::Libcamera { .. } => vec!["libcamerasrc"],
+            VideoSource::Rtsp { .. } => vec!["rtspsrc", "decodebin"],
+            VideoSource::File { .. } => vec!["filesrc", "decodebin"],
+            VideoSource::Test { .. } => vec!["videotestsrc"],
+        }
+    }
+
+    /// gst-launch description of the source, producing raw video.
+    pub fn launch_fragment(&self) -> String {
+        match self {
+            VideoSource::V4l2 { device } => match device {
+                Some(device) => format!("v4l2src device={}", quote(device)),
+                None => String::from("v4l2src"),
+            },
+            VideoSource::Libcamera { camera_name } => match camera_name {
+                Some(camera_name) => format!("libcamerasrc camera-name={}", quote(camera_name)),
+                None => String::from("libcamerasrc"),
+            },
+            VideoSource::Rtsp { location, latency } => format!(
+                "rtspsrc location={} latency={} ! decodebin",
+                quote(location),
+                latency.unwrap_or(200)
+            ),
+            VideoSource::File { location } => {
+                format!("filesrc location={} ! decodebin", quote(location))
+            }
+            VideoSource::Test { pattern } => format!(
+                "videotestsrc is-live=true pattern={}",
+                quote(pattern.as_deref().unwrap_or("smpte"))
+            ),
+        }
+    }
+
+    /// Adds the source elements to `pipeline` and links them to `downstream`.
+    /// Decoding sources expose their pads at runtime, the link is then made
+    /// once a raw video pad appears.
+    pub fn build(
+        &self,
+        pipeline: &gst::Pipeline,
+        downstream: &gst::Element,
+    ) -> Result<(), PipelineError> {
+        match self {
+            VideoSource::V4l2 { device } => {
+                let mut builder = gst::ElementFactory::make("v4l2src").name("source");
+                if let Some(device) = device {
+                    builder = builder.property("device", device);
+                }
+                add_and_link(pipeline, build_element(builder, "v4l2src")?, downstream)
+            }
+            VideoSource::Libcamera { camera_name } => {
+                let mut builder = gst::ElementFactory::make("libcamerasrc").name("source");
+                if let Some(camera_name) = camera_name {
+                    builder = builder.property("camera-name", camera_name);
+                }
+                add_and_link(
+                    pipeline,
+                    build_element(builder, "libcamerasrc")?,
+                    downstream,
+                )
+            }
+            VideoSource::Rtsp { location, latency } => {
+                let source = build_element(
+                    gst::ElementFactory::make("rtspsrc")
+                        .name("source")
+                        .property("location", location)
+                        .property("latency", latency.unwrap_or(200)),
+                    "rtspsrc",
+                )?;
+                let decoder = make_element("decodebin", "decoder")?;
+                pipeline
+                    .add_many([&source, &decoder])
+                    .map_err(|err| PipelineError::Assembly(err.to_string()))?;
+
+                // rtspsrc exposes one pad per RTP stream, only video is decoded
+                let decoder_weak = decoder.downgrade();
+                source.connect_pad_added(move |_, pad| {
+                    let Some(decoder) = decoder_weak.upgrade() else {
+                        return;
+                    };
+                    if pad_has_caps(pad, |structure| {
+                        structure.get::<&str>("media").ok() == Some("video")
+                    }) {
+                        link_pad_to(pad, &decoder);
+                    }
+                });
+                link_decoded_video(&decoder, downstream);
+
+                Ok(())
+            }
+            VideoSource::File { location } => {
+                let source = build_element(
+                    gst::ElementFactory::make("filesrc")
+                        .name("source")
+                        .property("location", location),
+                    "filesrc",
+                )?;
+                let decoder = make_element("decodebin", "decoder")?;
+                pipeline
+                    .add_many([&source, &decoder])
+                    .map_err(|err| PipelineError::Assembly(err.to_string()))?;
+                link_elements(&[&source, &decoder])?;
+                link_decoded_video(&decoder, downstream);
+
+                Ok(())
+            }
+            VideoSource::Test { pattern } => {
+                let source = build_element(
+                    gst::ElementFactory::make("videotestsrc")
+                        .name("source")
+                        .property("is-live", true),
+                    "videotestsrc",
+                )?;
+                set_property_from_str(&source, "pattern", pattern.as_deref().unwrap_or("smpte"))?;
+                add_and_link(pipeline, source, downstream)
+            }
+        }
+    }
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn build_element(
+    builder: gst::element_factory::ElementBuilder<'_>,
+    factory: &str,
+) -> Result<gst::Element, PipelineError> {
+    builder
+        .build()
+        .map_err(|err| PipelineError::ElementCreation(factory.to_owned(), err.to_string()))
+}
+
+fn add_and_link(
+    pipeline: &gst::Pipeline,
+    source: gst::Element,
+    downstream: &gst::Element,
+) -> Result<(), PipelineError> {
+    pipeline
+        .add(&source)
+        .map_err(|err| PipelineError::Assembly(err.to_string()))?;
+    link_elements(&[&source, downstream])
+}
+
+/// Links the first raw video pad exposed by `decoder` to `downstream`.
+fn link_decoded_video(decoder: &gst::Element, downstream: &gst::Element) {
+    let downstream_weak = downstream.downgrade();
+    decoder.connect_pad_added(move |_, pad| {
+        let Some(downstream) = downstream_weak.upgrade() else {
+            return;
+        };
+        if pad_has_caps(pad, |structure| structure.name().starts_with("video/x-raw")) {
+            link_pad_to(pad, &downstream);
+        }
+    });
+}
+
+fn pad_has_caps(pad: &gst::Pad, predicate: impl Fn(&gst::StructureRef) -> bool) -> bool {
+    let caps = pad.current_caps().unwrap_or_else(|| pad.query_caps(None));
+    caps.structure(0).map(predicate).unwrap_or(false)
+}
+
+fn link_pad_to(pad: &gst::Pad, element: &gst::Element) {
+    let Some(sink_pad) = element.static_pad("sink") else {
+        return;
+    };
+    if sink_pad.is_linked() {
+        return;
+    }
+    if let Err(err) = pad.link(&sink_pad) {
+        tracing::warn!(
+            "Failed to link {} to {}: {:?}",
+            pad.name(),
+            element.name(),
+            err
+        );
+    }
+}
@@ -2,12 +2,13 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use super::user_entity::User;
+use super::user_entity::{Role, User};
 
 #[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct UserDto {
     pub id: Uuid,
     pub username: String,
+    pub role: Role,
 }
 
 #[derive(Debug, Deserialize, JsonSchema)]
@@ -15,6 +16,13 @@ pub struct UserCreateDto {
     pub id: Option<Uuid>,
     pub username: String,
     pub password: String,
+    /// `viewer` when unset, the first account is always an admin.
+    pub role: Option<Role>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct UserRoleDto {
+    pub role: Role,
 }
 
 pub fn get_user_from_dto(user_dto: UserCreateDto, password_hash: String) -> User {
@@ -22,6 +30,7 @@ pub fn get_user_from_dto(user_dto: UserCreateDto, password_hash: String) -> User
         id: user_dto.id.unwrap_or(Uuid::new_v4()),
         username: user_dto.username,
         password_hash,
+        role: user_dto.role.unwrap_or(Role::Viewer),
     }
 }
 
@@ -29,5 +38,6 @@ pub fn get_user_dto(user: User) -> UserDto {
     UserDto {
         id: user.id,
         username: user.username,
+        role: user.role,
     }
 }
@@ -1,10 +1,49 @@
-use serde::Serialize;
+use std::str::FromStr;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+/// Permissions of a user, each role having those of the roles before it:
+/// viewers watch, operators also record, admins also manage users.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Operator,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Operator => "operator",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "viewer" => Ok(Role::Viewer),
+            "operator" => Ok(Role::Operator),
+            "admin" => Ok(Role::Admin),
+            _ => Err(format!("Unknown role: {}", value)),
+        }
+    }
+}
+
 // the output to our `create_user` handler
 #[derive(Clone, Serialize, Debug)]
 pub struct User {
     pub id: Uuid,
     pub username: String,
     pub password_hash: String,
+    pub role: Role,
 }
@@ -1,16 +1,25 @@
 use axum::{
     extract::{Path, State},
     http::StatusCode,
+    middleware,
     response::{IntoResponse, Response},
-    routing::{get, post},
+    routing::{delete, get, post, put},
     Json, Router,
 };
 use uuid::Uuid;
 
-use crate::{application::ApplicationState, service::ServiceType};
+use crate::{
+    application::ApplicationState,
+    features::{
+        api_keys::api_key_routes,
+        auth::{auth_entity::AuthenticatedUser, auth_middleware::require_role},
+    },
+    service::ServiceType,
+};
 
 use super::{
-    user_dto::{get_user_dto, get_user_from_dto, UserCreateDto, UserDto},
+    user_dto::{get_user_dto, get_user_from_dto, UserCreateDto, UserDto, UserRoleDto},
+    user_entity::Role,
     user_service::{UserService, UserServiceError},
 };
 
@@ -18,6 +27,9 @@ impl IntoResponse for UserServiceError {
     fn into_response(self) -> Response {
         let status_code = match self {
             UserServiceError::UserNotFound(_) => StatusCode::NOT_FOUND,
+            UserServiceError::AuthenticationRequired => StatusCode::UNAUTHORIZED,
+            UserServiceError::Forbidden => StatusCode::FORBIDDEN,
+            UserServiceError::LastAdmin => StatusCode::CONFLICT,
             UserServiceError::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR,
             UserServiceError::DatabaseError(_) => StatusCode::BAD_REQUEST,
         };
@@ -30,31 +42,64 @@ impl IntoResponse for UserServiceError {
 pub fn routes(state: ApplicationState) -> Router {
     let user_service = match state.service_provider.get_service("UserService") {
         Some(ServiceType::UserService(user_service)) => user_service,
-        None => panic!("UserService not found in ServiceProvider"),
+        _ => panic!("UserService not found in ServiceProvider"),
     };
 
+    let admin_routes = Router::new()
+        .route("/", get(handle_list_users))
+        .route("/:id", delete(handle_delete_user))
+        .route("/:id/role", put(handle_update_user_role))
+        .route_layer(middleware::from_fn_with_state(Role::Admin, require_role));
+
     Router::new()
-        .route("/", post(handle_create_user).get(handle_list_users))
-        .route("/:id", get(handle_read_user).delete(handle_delete_user))
+        .route("/", post(handle_create_user))
+        .route("/:id", get(handle_read_user))
+        .merge(admin_routes)
+        .nest_service("/:id/api-keys", api_key_routes::routes(state))
         .with_state(user_service)
 }
 
+/// Accounts are created by admins, but the first one needs no token so that
+/// a fresh install can be set up, and it is made an admin.
 async fn handle_create_user(
     State(service): State<UserService>,
+    user: Option<AuthenticatedUser>,
     Json(user_dto): Json<UserCreateDto>,
 ) -> Result<impl IntoResponse, UserServiceError> {
+    let is_admin = user.as_ref().is_some_and(|user| user.permits(Role::Admin));
+    // Only the first account, which becomes admin, is open to anyone. Checked
+    // again atomically on creation, this spares hashing for refused requests
+    let refusal = match user {
+        None => UserServiceError::AuthenticationRequired,
+        Some(_) => UserServiceError::Forbidden,
+    };
+    if !is_admin && service.has_users().await? {
+        return Err(refusal);
+    }
+
     let hashed_password = crypto_utils::hash_password(&user_dto.password)
         .map_err(|_| UserServiceError::InternalServerError)?;
 
-    let created_user = service
-        .create_user(get_user_from_dto(user_dto, hashed_password))
-        .await?;
+    let new_user = get_user_from_dto(user_dto, hashed_password);
+    let created_user = match is_admin {
+        true => service.create_user(new_user).await?,
+        false => service
+            .create_first_user(new_user)
+            .await
+            .map_err(|err| match err {
+                UserServiceError::AuthenticationRequired => refusal,
+                err => err,
+            })?,
+    };
     let user_dto = get_user_dto(created_user);
 
     Ok((StatusCode::CREATED, Json(user_dto)))
 }
 
-async fn handle_list_users(State(service): State<UserService>) -> impl IntoResponse {
+async fn handle_list_users(
+    State(service): State<UserService>,
+    _user: AuthenticatedUser,
+) -> impl IntoResponse {
     match service.list_users().await {
         Ok(users) => {
             let user_dtos: Vec<UserDto> = users.into_iter().map(get_user_dto).collect();
@@ -64,18 +109,36 @@ async fn handle_list_users(State(service): State<UserService>) -> impl IntoRespo
     }
 }
 
+/// Admins read any account, other users only their own.
 async fn handle_read_user(
     State(service): State<UserService>,
+    user: AuthenticatedUser,
     Path(id): Path<Uuid>,
 ) -> Result<impl IntoResponse, UserServiceError> {
+    if user.id != id && !user.permits(Role::Admin) {
+        return Err(UserServiceError::Forbidden);
+    }
+
     let user = service.read_user(id).await?;
     let user_dto = get_user_dto(user);
 
     Ok((StatusCode::OK, Json(user_dto)))
 }
 
+async fn handle_update_user_role(
+    State(service): State<UserService>,
+    Path(id): Path<Uuid>,
+    Json(user_role_dto): Json<UserRoleDto>,
+) -> Result<impl IntoResponse, UserServiceError> {
+    let user = service.update_user_role(id, user_role_dto.role).await?;
+    let user_dto = get_user_dto(user);
+
+    Ok((StatusCode::OK, Json(user_dto)))
+}
+
 async fn handle_delete_user(
     State(service): State<UserService>,
+    _user: AuthenticatedUser,
     Path(id): Path<Uuid>,
 ) -> impl IntoResponse {
     match service.delete_user(id).await {
@@ -85,9 +148,14 @@ async fn handle_delete_user(
 }
 
 pub mod crypto_utils {
+    use std::sync::OnceLock;
+
     use argon2::{
         self,
-        password_hash::{rand_core::OsRng, PasswordHasher, SaltString},
+        password_hash::{
+            rand_core::{OsRng, RngCore},
+            PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+        },
         Argon2,
     };
 
@@ -99,4 +167,30 @@ pub mod crypto_utils {
 
         Ok(hashed_password.to_string())
     }
+
+    /// Whether `password` matches `password_hash`, a malformed hash never
+    /// matches.
+    pub fn verify_password(password: &str, password_hash: &str) -> bool {
+        PasswordHash::new(password_hash).is_ok_and(|password_hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &password_hash)
+                .is_ok()
+        })
+    }
+
+    /// Random hex string of `length` bytes, for opaque tokens.
+    pub fn generate_token(length: usize) -> String {
+        let mut bytes = vec![0u8; length];
+        OsRng.fill_bytes(&mut bytes);
+
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    /// Hash of no actual password, verified against when a user is unknown
+    /// so that lookup takes as long as for a known user.
+    pub fn dummy_password_hash() -> &'static str {
+        static DUMMY_PASSWORD_HASH: OnceLock<String> = OnceLock::new();
+
+        DUMMY_PASSWORD_HASH.get_or_init(|| hash_password("dummy password").unwrap_or_default())
+    }
 }
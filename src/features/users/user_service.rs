@@ -1,16 +1,30 @@
-use sea_orm::{ActiveModelTrait, ActiveValue::NotSet, DatabaseConnection, EntityTrait, Set};
+use chrono::Utc;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::NotSet, ColumnTrait, ConnectionTrait, DatabaseConnection,
+    DatabaseTransaction, EntityName, EntityTrait, PaginatorTrait, QueryFilter, Set,
+    TransactionTrait,
+};
 use std::sync::Arc;
 use thiserror::Error;
 use uuid::Uuid;
 
 use crate::service::Service;
 
-use super::{user_entity::User, user_record::ActiveModel, user_record::Entity as UserRecord};
+use super::{
+    user_entity::{Role, User},
+    user_record::{self, ActiveModel, Entity as UserRecord},
+};
 
 #[derive(Debug, Error)]
 pub enum UserServiceError {
     #[error("User with id {0} not found")]
     UserNotFound(Uuid),
+    #[error("Only the first account can be created without authentication")]
+    AuthenticationRequired,
+    #[error("Insufficient role")]
+    Forbidden,
+    #[error("The last admin cannot lose their role")]
+    LastAdmin,
     #[allow(dead_code)]
     #[error("Internal server error")]
     InternalServerError,
@@ -40,24 +54,14 @@ impl UserService {
 
     pub async fn create_user(&self, user: User) -> Result<User, UserServiceError> {
         if let Some(conn) = &self.connection {
-            let new_user = ActiveModel {
-                id: Set(user.id),
-                username: Set(user.username),
-                password_hash: Set(user.password_hash),
-                created_at: NotSet,
-                updated_at: NotSet,
-            };
+            let new_user = get_record_from_user(user);
 
             let inserted_user = new_user.insert(conn.as_ref()).await.map_err(|err| {
                 println!("A database error occurred: {}", err.to_string());
                 UserServiceError::DatabaseError(err.to_string())
             })?;
 
-            Ok(User {
-                id: inserted_user.id,
-                username: inserted_user.username,
-                password_hash: inserted_user.password_hash,
-            })
+            Ok(get_user_from_record(inserted_user))
         } else {
             Err(UserServiceError::InternalServerError)
         }
@@ -69,19 +73,138 @@ impl UserService {
             .as_ref()
             .ok_or(UserServiceError::InternalServerError)?;
 
-        let user_record = UserRecord::find()
+        let user_record = UserRecord::find_by_id(id)
             .one(connection.as_ref())
             .await
             .map_err(|err| UserServiceError::DatabaseError(err.to_string()))?
             .ok_or(UserServiceError::UserNotFound(id))?;
 
-        let user = User {
-            id: user_record.id,
-            username: user_record.username,
-            password_hash: user_record.password_hash,
-        };
+        Ok(get_user_from_record(user_record))
+    }
+
+    /// Creates the first account, as an admin. Fails with
+    /// `AuthenticationRequired` once any account exists.
+    pub async fn create_first_user(&self, user: User) -> Result<User, UserServiceError> {
+        let transaction = self.begin_locked().await?;
+
+        let user_count = UserRecord::find()
+            .count(&transaction)
+            .await
+            .map_err(|err| UserServiceError::DatabaseError(err.to_string()))?;
+        if user_count > 0 {
+            return Err(UserServiceError::AuthenticationRequired);
+        }
+
+        let new_user = get_record_from_user(User {
+            role: Role::Admin,
+            ..user
+        });
+        let inserted_user = new_user
+            .insert(&transaction)
+            .await
+            .map_err(|err| UserServiceError::DatabaseError(err.to_string()))?;
+        transaction
+            .commit()
+            .await
+            .map_err(|err| UserServiceError::DatabaseError(err.to_string()))?;
+
+        Ok(get_user_from_record(inserted_user))
+    }
+
+    /// Changes the role of a user, keeping at least one admin.
+    pub async fn update_user_role(&self, id: Uuid, role: Role) -> Result<User, UserServiceError> {
+        let transaction = self.begin_locked().await?;
+
+        let user_record = UserRecord::find_by_id(id)
+            .one(&transaction)
+            .await
+            .map_err(|err| UserServiceError::DatabaseError(err.to_string()))?
+            .ok_or(UserServiceError::UserNotFound(id))?;
+        if user_record.role == Role::Admin.as_str() && role != Role::Admin {
+            let admin_count = UserRecord::find()
+                .filter(user_record::Column::Role.eq(Role::Admin.as_str()))
+                .count(&transaction)
+                .await
+                .map_err(|err| UserServiceError::DatabaseError(err.to_string()))?;
+            if admin_count <= 1 {
+                return Err(UserServiceError::LastAdmin);
+            }
+        }
+
+        let user_record = ActiveModel {
+            id: Set(id),
+            role: Set(role.as_str().to_owned()),
+            updated_at: Set(Utc::now()),
+            ..Default::default()
+        }
+        .update(&transaction)
+        .await
+        .map_err(|err| UserServiceError::DatabaseError(err.to_string()))?;
+        transaction
+            .commit()
+            .await
+            .map_err(|err| UserServiceError::DatabaseError(err.to_string()))?;
+
+        Ok(get_user_from_record(user_record))
+    }
+
+    /// Starts a transaction holding the user table against concurrent
+    /// writes, so that checks on the accounts and the roles stay true until
+    /// it ends. Dropping it without committing rolls it back.
+    async fn begin_locked(&self) -> Result<DatabaseTransaction, UserServiceError> {
+        let connection = self
+            .connection
+            .as_ref()
+            .ok_or(UserServiceError::InternalServerError)?;
+
+        let transaction = connection
+            .begin()
+            .await
+            .map_err(|err| UserServiceError::DatabaseError(err.to_string()))?;
+        // Conflicts with itself and with every write, reads go on
+        transaction
+            .execute_unprepared(&format!(
+                r#"LOCK TABLE "{}" IN SHARE ROW EXCLUSIVE MODE"#,
+                UserRecord.table_name()
+            ))
+            .await
+            .map_err(|err| UserServiceError::DatabaseError(err.to_string()))?;
+
+        Ok(transaction)
+    }
+
+    /// Whether any account exists yet.
+    pub async fn has_users(&self) -> Result<bool, UserServiceError> {
+        let connection = self
+            .connection
+            .as_ref()
+            .ok_or(UserServiceError::InternalServerError)?;
+
+        let count = UserRecord::find()
+            .count(connection.as_ref())
+            .await
+            .map_err(|err| UserServiceError::DatabaseError(err.to_string()))?;
+
+        Ok(count > 0)
+    }
 
-        Ok(user)
+    /// Finds the user named `username`, `None` when there is none.
+    pub async fn find_user_by_username(
+        &self,
+        username: &str,
+    ) -> Result<Option<User>, UserServiceError> {
+        let connection = self
+            .connection
+            .as_ref()
+            .ok_or(UserServiceError::InternalServerError)?;
+
+        let user_record = UserRecord::find()
+            .filter(user_record::Column::Username.eq(username))
+            .one(connection.as_ref())
+            .await
+            .map_err(|err| UserServiceError::DatabaseError(err.to_string()))?;
+
+        Ok(user_record.map(get_user_from_record))
     }
 
     pub async fn list_users(&self) -> Result<Vec<User>, UserServiceError> {
@@ -97,11 +220,7 @@ impl UserService {
 
         let users = user_records
             .into_iter() // Consume the records directly, no need for `iter()`
-            .map(|user| User {
-                id: user.id,
-                username: user.username,
-                password_hash: user.password_hash,
-            })
+            .map(get_user_from_record)
             .collect();
 
         Ok(users)
@@ -111,3 +230,24 @@ impl UserService {
         Err(UserServiceError::UserNotFound(id))
     }
 }
+
+fn get_record_from_user(user: User) -> ActiveModel {
+    ActiveModel {
+        id: Set(user.id),
+        username: Set(user.username),
+        password_hash: Set(user.password_hash),
+        role: Set(user.role.as_str().to_owned()),
+        created_at: NotSet,
+        updated_at: NotSet,
+    }
+}
+
+fn get_user_from_record(user_record: user_record::Model) -> User {
+    User {
+        id: user_record.id,
+        username: user_record.username,
+        password_hash: user_record.password_hash,
+        // Least privilege for values this version does not know
+        role: user_record.role.parse().unwrap_or(Role::Viewer),
+    }
+}
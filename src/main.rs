@@ -2,7 +2,6 @@ use std::error::Error;
 
 use application::Application;
 use configuration::load_config;
-use features::streams::pipeline::create_stream_pipeline;
 
 mod application;
 mod configuration;
@@ -17,13 +16,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let configuration = load_config()?;
 
     let application = Application::new(&configuration).initialize_state().await?;
-
-    // Handle any one-time setup like pipelines
-    if configuration.media.enabled {
-        if let Err(err) = create_stream_pipeline() {
-            eprintln!("Failed to create pipeline: {}", err);
-        }
-    }
+    let router = application.build_router()?;
 
     let application_name = &application.name;
     let address = format!("{}:{}", configuration.api.local_ip, configuration.api.port);
@@ -34,7 +27,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
     println!("{application_name} listening on {local_address}");
     println!("API docs are accessible at {local_address}/docs");
 
-    axum::serve(listener, application.build_router()).await?;
+    axum::serve(listener, router).await?;
 
     Ok(())
 }
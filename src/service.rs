@@ -1,6 +1,12 @@
 use std::collections::HashMap;
 use std::sync::Mutex;
 
+use crate::features::api_keys::api_key_service::ApiKeyService;
+use crate::features::auth::auth_service::AuthService;
+use crate::features::recordings::recording_service::RecordingService;
+use crate::features::snapshots::snapshot_service::SnapshotService;
+use crate::features::streams::recording_manager::RecordingManager;
+use crate::features::streams::stream_service::StreamService;
 use crate::features::users::user_service::UserService;
 
 pub trait Service {
@@ -9,13 +15,25 @@ pub trait Service {
 
 #[derive(Clone)]
 pub enum ServiceType {
+    ApiKeyService(ApiKeyService),
+    AuthService(AuthService),
     UserService(UserService),
+    RecordingManager(RecordingManager),
+    RecordingService(RecordingService),
+    SnapshotService(SnapshotService),
+    StreamService(StreamService),
 }
 
 impl ServiceType {
     pub fn name(&self) -> String {
         match self {
+            ServiceType::ApiKeyService(service) => service.name(),
+            ServiceType::AuthService(service) => service.name(),
             ServiceType::UserService(service) => service.name(),
+            ServiceType::RecordingManager(service) => service.name(),
+            ServiceType::RecordingService(service) => service.name(),
+            ServiceType::SnapshotService(service) => service.name(),
+            ServiceType::StreamService(service) => service.name(),
         }
     }
 }